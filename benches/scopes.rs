@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+use event_driven::util::scope::Scope;
+use event_driven::util::variables::Variable;
+use event_driven::VM;
+
+//One listener with a lot of locals that are read and reassigned over and over, run with cargo bench
+fn script(vars: usize, sets: usize) -> String {
+    let declarations = (0..vars).map(|idx| format!("    v{} (i32) = {};\n", idx, idx)).collect::<String>();
+    let assignments = (0..sets).map(|idx| format!("    v{} <- v{};\n", (idx * 7) % vars, (idx * 13) % vars)).collect::<String>();
+    format!("OnStart {{\n{}{}}}\n", declarations, assignments)
+}
+
+//The baseline, variables in a Vec that is searched front to back like the scopes used to be
+#[derive(Default)]
+struct LinearScope {
+    stack: Vec<Variable>
+}
+
+impl LinearScope {
+    fn declare(&mut self, var: Variable) {
+        self.stack.push(var);
+    }
+
+    fn get(&self, name: &String) -> Option<&Variable> {
+        self.stack.iter().find(|var| var.name == *name)
+    }
+
+    fn get_mut(&mut self, name: &String) -> Option<&mut Variable> {
+        self.stack.iter_mut().find(|var| var.name == *name)
+    }
+}
+
+fn variables(vars: usize) -> Vec<Variable> {
+    (0..vars).map(|idx| Variable {
+        name: format!("v{}", idx),
+        variable_type: event_driven::VariableType::I32(Some(idx as i32)),
+        nullable: false
+    }).collect()
+}
+
+//The same reads and writes as the script, without the VM around them
+fn assignments(vars: usize, sets: usize) -> Vec<(String, String)> {
+    (0..sets).map(|idx| (format!("v{}", (idx * 7) % vars), format!("v{}", (idx * 13) % vars))).collect()
+}
+
+fn hashed(vars: usize, sets: usize) -> Duration {
+    let assignments = assignments(vars, sets);
+    let start = Instant::now();
+    let mut scope = Scope::default();
+    variables(vars).into_iter().for_each(|var| scope.declare(var));
+    for (target, source) in &assignments {
+        let value = scope.get(source).unwrap().variable_type.clone();
+        scope.get_mut(target).unwrap().variable_type = value;
+    }
+    start.elapsed()
+}
+
+fn linear(vars: usize, sets: usize) -> Duration {
+    let assignments = assignments(vars, sets);
+    let start = Instant::now();
+    let mut scope = LinearScope::default();
+    variables(vars).into_iter().for_each(|var| scope.declare(var));
+    for (target, source) in &assignments {
+        let value = scope.get(source).unwrap().variable_type.clone();
+        scope.get_mut(target).unwrap().variable_type = value;
+    }
+    start.elapsed()
+}
+
+fn main() {
+    for (vars, sets) in [(100, 1000), (1000, 10000), (4000, 40000)] {
+        let vm = VM::builder().source(script(vars, sets).as_str()).build().unwrap();
+        let start = Instant::now();
+        vm.start().unwrap();
+        let elapsed = start.elapsed();
+        println!("{:>5} variables, {:>6} assignments: vm {:?}, hashed scope {:?}, linear scope {:?}", vars, sets, elapsed, hashed(vars, sets), linear(vars, sets));
+    }
+}
@@ -0,0 +1,280 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, GenericArgument, Ident, LitStr, PathArguments, Type};
+
+// #[derive(Event)]
+// #[event(name = "+", description = "Adds num2 to num1", call = "add")]
+// pub struct MathAdd {
+//     #[param(typ = "number")]
+//     num1: Option<i128>,
+//     #[output]
+//     result: Option<i128>
+// }
+//
+// Params are accepted in declaration order, every param and output field must be an Option of a script type
+#[proc_macro_derive(Event, attributes(event, param, output))]
+pub fn derive_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into()
+    }
+}
+
+struct EventAttr {
+    name: Option<LitStr>,
+    description: Option<LitStr>,
+    call: Option<Ident>
+}
+
+struct Field {
+    ident: Ident,
+    variant: Ident,
+    param: Option<FieldAttr>,
+    output: Option<FieldAttr>
+}
+
+#[derive(Default)]
+struct FieldAttr {
+    name: Option<LitStr>,
+    typ: Option<LitStr>,
+    default: Option<LitStr>
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let event = parse_event_attr(&input)?;
+    let name = event.name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let description = event.description.unwrap_or_else(|| LitStr::new("", ident.span()));
+
+    let struct_fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect::<Vec<_>>(),
+            Fields::Unit => vec![],
+            Fields::Unnamed(_) => return Err(syn::Error::new_spanned(ident, "Event can only be derived for structs with named fields"))
+        },
+        _ => return Err(syn::Error::new_spanned(ident, "Event can only be derived for structs"))
+    };
+
+    let all_fields = struct_fields.iter().map(|field| field.ident.clone().unwrap()).collect::<Vec<_>>();
+    let fields = struct_fields.iter().try_fold(vec![], |mut fields, field| -> syn::Result<Vec<Field>> {
+        let param = parse_field_attr(field, "param")?;
+        let output = parse_field_attr(field, "output")?;
+        if param.is_some() || output.is_some() {
+            fields.push(Field {
+                ident: field.ident.clone().unwrap(),
+                variant: variant_of(&field.ty)?,
+                param,
+                output
+            });
+        }
+        Ok(fields)
+    })?;
+
+    let params = fields.iter().filter(|field| field.param.is_some()).collect::<Vec<_>>();
+    let outputs = fields.iter().filter(|field| field.output.is_some()).collect::<Vec<_>>();
+
+    let schema_params = params.iter().map(|field| {
+        let attr = field.param.as_ref().unwrap();
+        let name = field_name(field, attr);
+        let typ = attr.typ.as_ref().map(|typ| typ.value()).unwrap_or_else(|| type_name(&field.variant));
+        match &attr.default {
+            Some(default) => quote!(::event_driven::event::schema::EventField::with_default(#name, #typ, #default)),
+            None => quote!(::event_driven::event::schema::EventField::new(#name, #typ))
+        }
+    });
+    let schema_outputs = outputs.iter().map(|field| {
+        let attr = field.output.as_ref().unwrap();
+        let name = field_name(field, attr);
+        let typ = attr.typ.as_ref().map(|typ| typ.value()).unwrap_or_else(|| format!("?{}", type_name(&field.variant)));
+        quote!(::event_driven::event::schema::EventField::new(#name, #typ))
+    });
+
+    let get_var = outputs.iter().map(|field| {
+        let name = field_name(field, field.output.as_ref().unwrap());
+        let field_ident = &field.ident;
+        let variant = &field.variant;
+        quote!(#name => Some(::event_driven::util::variables::VariableType::#variant(self.#field_ident.clone())))
+    });
+
+    let set_var = outputs.iter().map(|field| {
+        let name = field_name(field, field.output.as_ref().unwrap());
+        let field_ident = &field.ident;
+        let variant = &field.variant;
+        quote! {
+            (#name, ::event_driven::util::variables::VariableType::#variant(value)) => {
+                self.#field_ident = value;
+                true
+            }
+        }
+    });
+
+    let accept = params.iter().enumerate().map(|(idx, field)| {
+        let idx = idx as u8;
+        let field_ident = &field.ident;
+        let variant = &field.variant;
+        let typ = field.param.as_ref().unwrap().typ.as_ref().map(|typ| typ.value());
+        match typ.as_deref().map(|typ| typ.trim_start_matches('?')) {
+            Some("number") => quote! {
+                #idx => {
+                    if !::event_driven::util::variables::fits_number(&param) {
+                        return false;
+                    }
+                    self.#field_ident = ::event_driven::util::variables::type_value_to_number(&param);
+                    true
+                }
+            },
+            Some("any") => quote! {
+                #idx => {
+                    self.#field_ident = ::event_driven::util::variables::type_value_to_string(&param);
+                    true
+                }
+            },
+            _ => quote! {
+                #idx => {
+                    if let ::event_driven::util::variables::VariableType::#variant(value) = param {
+                        self.#field_ident = value;
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+        }
+    });
+
+    let call = match &event.call {
+        Some(call) => quote!(self.#call();),
+        None => quote!()
+    };
+
+    Ok(quote! {
+        impl ::event_driven::event::standard_events::Event for #ident {
+            fn name(&self) -> &str {
+                #name
+            }
+            fn schema(&self) -> ::event_driven::event::schema::EventSchema {
+                ::event_driven::event::schema::EventSchema::new(#name, #description)
+                    #(.param(#schema_params))*
+                    #(.output(#schema_outputs))*
+            }
+            fn check_param_count(&self, count: u8) -> bool {
+                self.schema().check_param_count(count as usize)
+            }
+            fn get_var(&self, name: &str) -> Option<::event_driven::util::variables::VariableType> {
+                match name {
+                    #(#get_var,)*
+                    _ => None
+                }
+            }
+            #[allow(unused_variables)]
+            fn set_var(&mut self, name: &str, value: ::event_driven::util::variables::VariableType) -> bool {
+                match (name, value) {
+                    #(#set_var,)*
+                    _ => false
+                }
+            }
+            #[allow(unused_variables)]
+            fn accept(&mut self, idx: u8, param: ::event_driven::util::variables::VariableType) -> bool {
+                match idx {
+                    #(#accept,)*
+                    _ => false
+                }
+            }
+            fn call(&mut self) {
+                #call
+            }
+            fn clone_self(&self) -> Box<dyn ::event_driven::event::standard_events::Event> {
+                Box::new(#ident {
+                    #(#all_fields: self.#all_fields.clone()),*
+                })
+            }
+        }
+    })
+}
+
+fn parse_event_attr(input: &DeriveInput) -> syn::Result<EventAttr> {
+    let mut event = EventAttr {
+        name: None,
+        description: None,
+        call: None
+    };
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("event")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                event.name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("description") {
+                event.description = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("call") {
+                let call: LitStr = meta.value()?.parse()?;
+                event.call = Some(call.parse()?);
+            } else {
+                return Err(meta.error("Unknown event attribute, expected name, description or call"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(event)
+}
+
+fn parse_field_attr(field: &syn::Field, kind: &str) -> syn::Result<Option<FieldAttr>> {
+    field.attrs.iter().filter(|attr| attr.path().is_ident(kind)).try_fold(None, |_, attr| {
+        let mut field_attr = FieldAttr::default();
+        if let syn::Meta::List(_) = attr.meta {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    field_attr.name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("typ") {
+                    field_attr.typ = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("default") && kind == "param" {
+                    field_attr.default = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error(format!("Unknown {} attribute", kind)));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(Some(field_attr))
+    })
+}
+
+fn field_name(field: &Field, attr: &FieldAttr) -> LitStr {
+    attr.name.clone().unwrap_or_else(|| LitStr::new(&field.ident.to_string(), field.ident.span()))
+}
+
+//Option<i128> -> I128
+fn variant_of(ty: &Type) -> syn::Result<Ident> {
+    let error = || syn::Error::new_spanned(ty, "Event fields must be an Option of u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64, char, bool or String");
+    let Type::Path(path) = ty else { return Err(error()) };
+    let segment = path.path.segments.last().ok_or_else(error)?;
+    if segment.ident != "Option" {
+        return Err(error());
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else { return Err(error()) };
+    let Some(GenericArgument::Type(Type::Path(inner))) = args.args.first() else { return Err(error()) };
+    let inner = inner.path.segments.last().ok_or_else(error)?.ident.to_string();
+    let variant = match inner.as_str() {
+        "u8" => "U8",
+        "u16" => "U16",
+        "u32" => "U32",
+        "u64" => "U64",
+        "u128" => "U128",
+        "i8" => "I8",
+        "i16" => "I16",
+        "i32" => "I32",
+        "i64" => "I64",
+        "i128" => "I128",
+        "f32" => "Float",
+        "f64" => "Double",
+        "char" => "Char",
+        "bool" => "Bool",
+        "String" => "String",
+        _ => return Err(error())
+    };
+    Ok(format_ident!("{}", variant))
+}
+
+fn type_name(variant: &Ident) -> String {
+    variant.to_string().to_lowercase()
+}
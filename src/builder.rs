@@ -0,0 +1,226 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::checker;
+use crate::event::event_pipeline::{EventListener, EventPipeline, MONITOR_PRIORITY};
+use crate::event::clock::Clock;
+use crate::event::registry::EventRegistry;
+use crate::event::scheduler::{Scheduler, SharedScheduler};
+use crate::event::schema::EventSchema;
+use crate::event::standard_events::Event;
+use crate::parser;
+use crate::util::catch_error;
+use crate::util::position::Position;
+use crate::vm::VM;
+
+#[derive(Debug)]
+pub enum BuildError {
+    //A source file couldn't be read
+    File(String),
+    //The first syntax error, the parser stops there (a few errors have no position)
+    Syntax(String, Option<Position>),
+    //Everything the checker found, or the first invalid enum, custom event, function or match
+    Check(Vec<(String, Position)>)
+}
+
+impl BuildError {
+    pub fn errors(&self) -> Vec<(String, Position)> {
+        match self {
+            BuildError::File(msg) => vec![(msg.clone(), Position::default())],
+            BuildError::Syntax(msg, pos) => vec![(msg.clone(), pos.unwrap_or_default())],
+            BuildError::Check(errors) => errors.clone()
+        }
+    }
+}
+
+//One error per line, like the binary prints them
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::File(msg) => write!(f, "{}", msg),
+            BuildError::Syntax(msg, Some(pos)) => write!(f, "{} (at {})", msg, pos),
+            BuildError::Syntax(msg, None) => write!(f, "{} (unknown position)", msg),
+            BuildError::Check(errors) => {
+                let errors = errors.iter().map(|(msg, pos)| format!("{} (at {})", msg, pos)).collect::<Vec<String>>();
+                write!(f, "{}", errors.join("\n"))
+            }
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+pub struct VMBuilder {
+    lines: Vec<String>,
+    registry: EventRegistry,
+    scheduler: SharedScheduler,
+    native_listeners: Vec<(String, EventListener)>,
+    warn_shadowing: bool,
+    file_error: Option<String>,
+    errors: Vec<(String, Position)>
+}
+
+impl VMBuilder {
+    pub fn new() -> Self {
+        let scheduler = Scheduler::shared();
+        VMBuilder {
+            lines: vec![],
+            registry: EventRegistry::new(&scheduler),
+            scheduler,
+            native_listeners: vec![],
+            warn_shadowing: false,
+            file_error: None,
+            errors: vec![]
+        }
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.lines.extend(source.lines().map(|line| line.to_string()));
+        self
+    }
+
+    pub fn file(mut self, path: &str) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(source) => self.source(source.as_str()),
+            Err(err) => {
+                self.file_error.get_or_insert(format!("Failed to read file {}: {}", path, err));
+                self
+            }
+        }
+    }
+
+    pub fn event<F: Fn() -> Box<dyn Event> + 'static>(mut self, name: &str, factory: F) -> Self {
+        if let Err(msg) = self.registry.register(name, factory) {
+            self.errors.push((msg, Position::default()));
+        }
+        self
+    }
+
+    //Defaults to a virtual clock that only moves when the VM is advanced
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        self.scheduler.borrow_mut().set_clock(Box::new(clock));
+        self
+    }
+
+    pub fn listener<F: Fn(&dyn Event) -> bool + 'static>(self, name: &str, listener: F) -> Self {
+        self.listener_with_priority(name, 0, listener)
+    }
+
+    //Script listeners have priority 0, higher priorities run first
+    pub fn listener_with_priority<F: Fn(&dyn Event) -> bool + 'static>(mut self, name: &str, priority: i32, listener: F) -> Self {
+        self.native_listeners.push((name.to_string(), EventListener::Native {
+            callback: Box::new(listener),
+            priority
+        }));
+        self
+    }
+
+    pub fn monitor<F: Fn(&dyn Event) + 'static>(self, name: &str, monitor: F) -> Self {
+        self.listener_with_priority(name, MONITOR_PRIORITY, move |event| {
+            monitor(event);
+            false
+        })
+    }
+
+    //Prints a warning when a variable shadows one of an outer scope
+    pub fn warn_shadowing(mut self, warn: bool) -> Self {
+        self.warn_shadowing = warn;
+        self
+    }
+
+    //Nothing here panics, the parser's errors are caught and returned like the checker's
+    pub fn build(self) -> Result<VM, BuildError> {
+        let VMBuilder { lines, mut registry, scheduler, native_listeners, warn_shadowing, file_error, mut errors } = self;
+        if let Some(msg) = file_error {
+            return Err(BuildError::File(msg));
+        }
+        let tokens = catch_error(|| parser::tokenize(parser::pre_parse(lines))).map_err(|error| BuildError::Syntax(error.message, error.pos))?;
+        let (enums, functions, custom_events) = catch_error(|| {
+            let enums = parser::enums(&tokens);
+            parser::check_matches(&tokens, &enums);
+            (enums, parser::functions(&tokens), parser::custom_events(&tokens))
+        }).map_err(|error| BuildError::Check(vec![(error.message, error.pos.unwrap_or_default())]))?;
+        //In declaration order, so the errors come out in the order of the source
+        let mut custom_events = custom_events.into_values().collect::<Vec<(EventSchema, Position)>>();
+        custom_events.sort_by_key(|(_, pos)| (pos.line, pos.index));
+        custom_events.into_iter().for_each(|(schema, pos)| {
+            if let Err(msg) = registry.register_custom(schema) {
+                errors.push((msg, pos));
+            }
+        });
+        let listeners = parser::split(tokens);
+        errors.extend(checker::check(&listeners, &functions, &enums, &registry, warn_shadowing));
+        if !errors.is_empty() {
+            return Err(BuildError::Check(errors));
+        }
+
+        let mut pipeline = EventPipeline {
+            listeners
+        };
+        native_listeners.into_iter().for_each(|(name, listener)| pipeline.add(&name, listener));
+
+        let vm = VM::new(pipeline, enums, registry, scheduler, functions);
+        vm.init_memory();
+        Ok(vm)
+    }
+}
+
+impl Default for VMBuilder {
+    fn default() -> Self {
+        VMBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::BuildError;
+    use crate::event::standard_events::OnStart;
+    use crate::vm::VM;
+
+    #[test]
+    fn syntax_errors_are_returned() {
+        match VM::builder().source("OnStart {\n    x (u8) = 1\n}\n").build() {
+            Err(BuildError::Syntax(msg, _)) => assert!(msg.contains("semicolon"), "{}", msg),
+            _ => panic!("expected a syntax error")
+        }
+    }
+
+    #[test]
+    fn invalid_priorities_are_syntax_errors() {
+        let error = VM::builder().source("OnStart [priority=urgent] {\n}\n").build().err().unwrap();
+        assert_eq!(error.to_string(), "Unknown listener priority urgent (expected lowest, low, normal, high, highest, monitor or a number) (at 1:8)");
+    }
+
+    #[test]
+    fn declaration_errors_are_check_errors() {
+        let source = "@enum {\n    S {\n        A;\n        B;\n    }\n}\nOnStart {\n    s (S) = S::A;\n    match s {\n        S::A {\n        }\n    }\n}\n";
+        assert!(matches!(VM::builder().source(source).build(), Err(BuildError::Check(_))));
+    }
+
+    #[test]
+    fn custom_event_collisions_point_at_the_declaration() {
+        let source = "@custom {\n    Ping {\n    }\n    Print {\n        text (string);\n    }\n}\n";
+        let error = VM::builder().event("Ping", || Box::new(OnStart)).source(source).build().err().unwrap();
+        assert_eq!(error.to_string(), "Event Ping is already registered (at 2:0)\nEvent Print is already registered (at 4:0)");
+    }
+
+    #[test]
+    fn unreadable_files_are_returned() {
+        assert!(matches!(VM::builder().file("/nonexistent/script.evd").build(), Err(BuildError::File(_))));
+    }
+
+    #[test]
+    fn fire_returns_runtime_errors() {
+        let vm = VM::builder().source("@custom {\n    Boom {\n    }\n}\nBoom {\n    y (u8) <= \"300\";\n}\n").build().unwrap();
+        let error = vm.fire("Boom", vec![]).unwrap_err();
+        assert_eq!(error.pos.map(|pos| pos.line), Some(6));
+        assert_eq!(error.to_string(), "Can't cast 300 to u8: out of range (at 6:10)");
+    }
+
+    #[test]
+    fn build_errors_display_their_positions() {
+        let error = VM::builder().source("OnStart {\n    x (u8) = \"a\";\n    #Nope <- 1;\n}\n").build().err().unwrap();
+        assert_eq!(error.to_string(), "\"a\" is not of type u8 (at 2:9)\nNo such event: Nope (at 3:9)");
+        let error: Box<dyn std::error::Error> = Box::new(error);
+        assert!(error.to_string().starts_with("\"a\""));
+    }
+}
@@ -0,0 +1,663 @@
+use std::collections::HashMap;
+use crate::event::event_pipeline::EventListener;
+use crate::event::registry::EventRegistry;
+use crate::event::schema::{EventField, EventSchema};
+use crate::event::standard_events::{check_exit_code, Event};
+use crate::util::position::Position;
+use crate::util::warning;
+use crate::util::token::{EventParam, Function, MatchArm, Token, TokenType};
+use crate::util::variables::{can_cast, CastMode, check_same_type, EnumType, field_access, function_call, is_literal_of_type, literal_value, type_from_name, type_to_name, VariableType};
+
+#[derive(Clone)]
+struct CheckedVariable {
+    typ: Option<VariableType>,
+    nullable: bool
+}
+
+struct Checker<'a> {
+    listener: &'a String,
+    //The function whose body is checked, None in listeners
+    function: Option<&'a Function>,
+    functions: &'a HashMap<String, Function>,
+    enums: &'a HashMap<String, EnumType>,
+    registry: &'a EventRegistry,
+    scopes: Vec<HashMap<String, CheckedVariable>>,
+    warn_shadowing: bool,
+    errors: Vec<(String, Position)>
+}
+
+//Shadowing is allowed, warn_shadowing only prints a warning for it
+pub fn check(listeners: &HashMap<String, Vec<EventListener>>, functions: &HashMap<String, Function>, enums: &HashMap<String, EnumType>, registry: &EventRegistry, warn_shadowing: bool) -> Vec<(String, Position)> {
+    //The @memory variables are the outermost scope of every listener
+    let memory_name = String::from("@memory");
+    let mut memory = Checker {
+        listener: &memory_name,
+        function: None,
+        functions,
+        enums,
+        registry,
+        scopes: vec![HashMap::new()],
+        warn_shadowing,
+        errors: vec![]
+    };
+    listeners.get(&memory_name).into_iter().flatten().for_each(|listener| if let EventListener::Script { tokens, .. } = listener {
+        tokens.iter()
+            .filter(|token| !matches!(token.token, TokenType::ScopeStart | TokenType::ScopeEnd))
+            .for_each(|token| memory.check_token(token));
+    });
+    let globals = memory.scopes.swap_remove(0);
+    //Functions don't handle an event, a name with a space can't be one
+    let function_names = functions.values().map(|function| (function, format!("fn {}", function.name))).collect::<Vec<(&Function, String)>>();
+
+    let mut errors = listeners.iter()
+        .filter(|(name, _)| !name.starts_with('@'))
+        .flat_map(|(name, listeners)| listeners.iter().filter_map(move |listener| match listener {
+            EventListener::Script { tokens, .. } => Some((name, tokens)),
+            EventListener::Native { .. } => None
+        }))
+        .flat_map(|(name, tokens)| {
+            let mut checker = Checker {
+                listener: name,
+                function: None,
+                functions,
+                enums,
+                registry,
+                scopes: vec![globals.clone()],
+                warn_shadowing,
+                errors: vec![]
+            };
+            checker.check_tokens(tokens);
+            checker.errors
+        })
+        .chain(function_names.iter().flat_map(|(function, name)| {
+            //A function's body sees the globals and its params, like at runtime
+            let mut checker = Checker {
+                listener: name,
+                function: Some(function),
+                functions,
+                enums,
+                registry,
+                scopes: vec![globals.clone()],
+                warn_shadowing,
+                errors: vec![]
+            };
+            let params = function.params.iter().map(|(param, typ)| {
+                let (typ, nullable) = checker.declared_type(typ, function.pos);
+                (param.clone(), CheckedVariable {
+                    typ,
+                    nullable
+                })
+            }).collect::<HashMap<String, CheckedVariable>>();
+            if let Some(returns) = &function.returns {
+                checker.declared_type(returns, function.pos);
+            }
+            checker.scopes.push(params);
+            checker.check_tokens(&function.tokens);
+            checker.errors
+        }))
+        .chain(memory.errors)
+        .collect::<Vec<(String, Position)>>();
+    errors.sort_by_key(|(_, pos)| (pos.line, pos.index));
+    errors
+}
+
+impl<'a> Checker<'a> {
+    fn check_tokens(&mut self, tokens: &[Token]) {
+        tokens.iter().for_each(|token| self.check_token(token));
+    }
+
+    fn check_token(&mut self, token: &Token) {
+        let pos = token.pos;
+        match &token.token {
+            TokenType::ScopeStart => {
+                self.scopes.push(HashMap::new());
+            }
+            TokenType::ScopeEnd => {
+                self.scopes.pop();
+            }
+            TokenType::InitVariable(name, typ, is_static_value, var_cast, value) => {
+                let (target, nullable) = self.declared_type(typ, pos);
+                if let Some(value) = value {
+                    if let Some(target) = &target {
+                        if *is_static_value {
+                            self.check_literal(name, target, nullable, value, pos);
+                        } else if let Some(source) = self.source_type(name, value, pos) {
+                            self.check_assign(name, target, &source, *var_cast, pos);
+                        }
+                    }
+                } else {
+                    self.error(format!("Missing initial value for variable {} (type {})", name, typ), pos);
+                }
+                self.declare(name, target, nullable, pos);
+            }
+            TokenType::InitVariableEvent(name, event, params) => {
+                let event = self.check_event_call(event, params, pos);
+                self.declare(name, event.map(VariableType::Event), false, pos);
+            }
+            TokenType::InitVariableOutputs(bindings, event, params) => {
+                let schema = self.check_event_call(event, params, pos).map(|event| event.schema());
+                bindings.iter().for_each(|(output, name)| {
+                    let (typ, nullable) = match &schema {
+                        Some(schema) => (self.output_type(schema, output, pos), schema.get_output(output).is_some_and(|field| field.typ.starts_with('?'))),
+                        None => (None, true)
+                    };
+                    self.declare(name, typ, nullable, pos);
+                });
+            }
+            TokenType::InitVariableEnum(name, typ, variant, params) => {
+                let (target, nullable) = self.declared_type(typ, pos);
+                let source = self.check_enum_value(variant, params, pos);
+                if let (Some(target), Some(source)) = (&target, &source) {
+                    self.check_assign(name, target, source, None, pos);
+                }
+                self.declare(name, target, nullable, pos);
+            }
+            TokenType::VariableStaticSet(name, value) => {
+                if let Some((Some(target), nullable)) = self.find(name, pos) {
+                    self.check_literal(name, &target, nullable, value, pos);
+                }
+            }
+            TokenType::VariableDynamicSet(name, source, var_cast) if self.is_current_event(name) => {
+                let target = self.current_event_field(source, pos);
+                if let (Some(target), Some((Some(source_type), _))) = (target, self.find(source, pos)) {
+                    self.check_assign(source, &target, &source_type, *var_cast, pos);
+                }
+            }
+            TokenType::VariableDynamicSet(name, source, var_cast) => {
+                if let Some((target, _)) = self.find(name, pos) {
+                    if let (Some(target), Some(source)) = (target, self.source_type(name, source, pos)) {
+                        self.check_assign(name, &target, &source, *var_cast, pos);
+                    }
+                }
+            }
+            TokenType::VariableEventSet(name, event, params) => {
+                let target = self.find(name, pos);
+                if let Some(event) = self.check_event_call(event, params, pos) {
+                    if let Some((Some(target), _)) = target {
+                        self.check_assign(name, &target, &VariableType::Event(event), None, pos);
+                    }
+                }
+            }
+            TokenType::VariableEnumSet(name, variant, params) => {
+                let target = self.find(name, pos);
+                if let Some(source) = self.check_enum_value(variant, params, pos) {
+                    if let Some((Some(target), _)) = target {
+                        self.check_assign(name, &target, &source, None, pos);
+                    }
+                }
+            }
+            TokenType::CallEvent(name, params) | TokenType::QueueEvent(name, params) => {
+                self.check_event_call(name, params, pos);
+            }
+            TokenType::Match(name, arms) => {
+                let enum_name = match self.find(name, pos) {
+                    Some((Some(VariableType::Enum(enum_name, _)), _)) => Some(enum_name),
+                    Some((Some(typ), _)) => {
+                        self.error(format!("Can't match on variable {} of type {}, it is not an enum", name, type_to_name(&typ)), pos);
+                        None
+                    }
+                    _ => None
+                };
+                arms.iter().for_each(|arm| self.check_match_arm(enum_name.as_ref(), arm));
+            }
+            TokenType::Try(tokens, catch, catch_tokens) => {
+                self.scopes.push(HashMap::new());
+                self.check_tokens(tokens);
+                self.scopes.pop();
+                if let Some(catch) = catch {
+                    let mut scope = HashMap::new();
+                    scope.insert(catch.clone(), CheckedVariable {
+                        typ: self.registry.get(&String::from("OnError")).map(VariableType::Event),
+                        nullable: false
+                    });
+                    self.scopes.push(scope);
+                    self.check_tokens(catch_tokens);
+                    self.scopes.pop();
+                } else {
+                    self.error(String::from("try block without catch"), pos);
+                }
+            }
+            TokenType::CallFunction(name, args) => {
+                self.check_call(name, args, pos);
+            }
+            TokenType::Return(value) => {
+                match (self.function, value) {
+                    (None, _) => self.error(String::from("return outside of a function"), pos),
+                    (Some(Function { returns: Some(typ), .. }), Some(value)) => self.check_typed_value(&String::from("return"), typ, value, pos),
+                    (Some(Function { returns: None, .. }), None) => {}
+                    (Some(function), Some(_)) => self.error(format!("Function {} doesn't return a value", function.name), pos),
+                    (Some(function), None) => self.error(format!("Function {} has to return a value of type {}", function.name, function.returns.clone().unwrap_or_default()), pos)
+                }
+            }
+            TokenType::Raw(s) => {
+                self.error(format!("Unparsed instruction: {}", s), pos);
+            }
+            _ => {}
+        }
+    }
+
+    fn check_match_arm(&mut self, enum_name: Option<&String>, arm: &MatchArm) {
+        let mut scope = HashMap::new();
+        if let Some((arm_enum, variant)) = arm.variant.split_once("::") {
+            if let Some(enum_name) = enum_name.filter(|enum_name| enum_name.as_str() != arm_enum) {
+                self.error(format!("Match arm {} doesn't belong to enum {}", arm.variant, enum_name), arm.pos);
+            }
+            if let Some(variant) = self.enums.get(arm_enum).and_then(|typ| typ.variants.iter().find(|v| v.name == variant)) {
+                arm.bindings.iter().zip(variant.fields.iter()).filter(|(binding, _)| binding.as_str() != "_").for_each(|(binding, (_, typ))| {
+                    scope.insert(binding.clone(), CheckedVariable {
+                        typ: type_from_name(typ.trim_start_matches('?')),
+                        nullable: typ.starts_with('?')
+                    });
+                });
+            }
+        }
+        self.scopes.push(scope);
+        self.check_tokens(&arm.tokens);
+        self.scopes.pop();
+    }
+
+    fn check_event_call(&mut self, name: &String, params: &Option<Vec<EventParam>>, pos: Position) -> Option<Box<dyn Event>> {
+        let event = self.registry.get(name);
+        if let Some(event) = event {
+            let schema = event.schema();
+            let Some(params) = self.bind(&schema, params, pos) else {
+                return Some(event);
+            };
+            params.iter().enumerate().for_each(|(idx, param)| {
+                if let (Some(typ), Some(field)) = (self.param_type(name, schema.get_param(idx), param, pos), schema.get_param(idx)) {
+                    if !field.accepts(&typ) {
+                        self.error(format!("Invalid event parameter {} for event {}: expected {} ({}), found {}", param, name, field.name, field.typ, type_to_name(&typ)), pos);
+                    }
+                }
+            });
+            if !schema.check_param_count(params.len()) {
+                self.error(format!("Incorrect event parameter count ({}) for event {}, expected {}", params.len(), name, schema.signature()), pos);
+            }
+            if let Some(target) = params.first().filter(|target| name == "Schedule" && !self.is_var(target)) {
+                self.check_timer(target, pos);
+            }
+            if let Some(code) = params.first().filter(|code| name == "Exit" && !self.is_var(code)).and_then(|code| code.parse::<i32>().ok()) {
+                if let Err(msg) = check_exit_code(code) {
+                    self.error(msg, pos);
+                }
+            }
+            Some(event)
+        } else {
+            self.error(format!("No such event: {}", name), pos);
+            None
+        }
+    }
+
+    //A literal event name given to Schedule, the timer fires it without params
+    fn check_timer(&mut self, target: &str, pos: Position) {
+        let Some(target) = target.strip_prefix('"').and_then(|target| target.strip_suffix('"')) else {
+            return;
+        };
+        match self.registry.get(&target.to_string()) {
+            Some(event) => if let Err(msg) = event.schema().bind(&[]) {
+                self.error(format!("Can't schedule {}: {}", target, msg), pos);
+            }
+            None => self.error(format!("Can't schedule {}: No such event: {}", target, target), pos)
+        }
+    }
+
+    fn check_enum_value(&mut self, variant: &String, params: &Option<Vec<EventParam>>, pos: Position) -> Option<VariableType> {
+        let enums = self.enums;
+        let (enum_name, variant_name) = variant.split_once("::")?;
+        let Some(enum_type) = enums.get(enum_name) else {
+            self.error(format!("No such enum: {}", enum_name), pos);
+            return None;
+        };
+        let Some(enum_variant) = enum_type.variants.iter().find(|v| v.name == variant_name) else {
+            self.error(format!("Enum {} has no variant {}", enum_name, variant_name), pos);
+            return None;
+        };
+
+        let Some(params) = self.bind(&enum_variant.schema(&enum_type.name), params, pos) else {
+            return Some(VariableType::Enum(enum_type.name.clone(), None));
+        };
+        if params.len() != enum_variant.fields.len() {
+            self.error(format!("Enum variant {} takes {} value(s), got {}", variant, enum_variant.fields.len(), params.len()), pos);
+        }
+        enum_variant.fields.iter().zip(params.iter()).for_each(|((field, typ), param)| {
+            if let Some(target) = type_from_name(typ.trim_start_matches('?')) {
+                if self.is_var(param) {
+                    if let Some(source) = self.source_type(field, param, pos) {
+                        self.check_assign(field, &target, &source, None, pos);
+                    }
+                } else {
+                    self.check_literal(field, &target, typ.starts_with('?'), param, pos);
+                }
+            }
+        });
+
+        Some(VariableType::Enum(enum_type.name.clone(), None))
+    }
+
+    fn check_literal(&mut self, name: &String, target: &VariableType, nullable: bool, value: &String, pos: Position) {
+        if value == "null" {
+            if !nullable {
+                self.error(format!("Can't assign null to non-null variable {}", name), pos);
+            }
+        } else if let VariableType::Enum(_, _) = target {
+            if !value.contains("::") {
+                self.error(format!("{} is not of type {}", value, type_to_name(target)), pos);
+            } else if let Some(source) = self.check_enum_value(value, &None, pos) {
+                self.check_assign(name, target, &source, None, pos);
+            }
+        } else if !is_literal_of_type(type_to_name(target).as_str(), value) {
+            self.error(format!("{} is not of type {}", value, type_to_name(target)), pos);
+        }
+    }
+
+    fn check_assign(&mut self, name: &String, target: &VariableType, source: &VariableType, cast: Option<CastMode>, pos: Position) {
+        if check_same_type(target, source) {
+            return;
+        }
+        if cast.is_some() && can_cast(source, target) {
+            return;
+        }
+        if cast.is_some() {
+            self.error(format!("Can't cast {} to {}", type_to_name(source), type_to_name(target)), pos);
+        } else {
+            self.error(format!("Can't assign to variable {}: expected {}, found {}", name, type_to_name(target), type_to_name(source)), pos);
+        }
+    }
+
+    fn bind(&mut self, schema: &EventSchema, params: &Option<Vec<EventParam>>, pos: Position) -> Option<Vec<String>> {
+        schema.bind(params.as_deref().unwrap_or_default()).map_err(|msg| self.error(msg, pos)).ok()
+    }
+
+    fn param_type(&mut self, name: &String, field: Option<&EventField>, source: &String, pos: Position) -> Option<VariableType> {
+        if !self.is_var(source) {
+            if let Some(value) = field.and_then(|field| literal_value(&field.typ, source)) {
+                return Some(value);
+            }
+        }
+        self.source_type(name, source, pos)
+    }
+
+    fn source_type(&mut self, name: &String, source: &String, pos: Position) -> Option<VariableType> {
+        if let Some((function, args)) = function_call(source) {
+            let returns = self.check_call(&function, &args, pos)?.returns.as_ref();
+            if returns.is_none() {
+                self.error(format!("Function {} doesn't return a value", function), pos);
+            }
+            returns.and_then(|typ| self.value_type(typ))
+        } else if let Some((var, field)) = field_access(source) {
+            self.field_type(&var, &field, pos)
+        } else if self.is_current_event(source) {
+            self.current_event_field(name, pos)
+        } else if let Some(var) = self.lookup(source) {
+            if let Some(VariableType::Event(event)) = &var.typ {
+                self.output_type(&event.schema(), name, pos)
+            } else {
+                var.typ.clone()
+            }
+        } else if source.len() >= 2 && source.starts_with('"') && source.ends_with('"') {
+            Some(VariableType::String(None))
+        } else if let Some(value) = literal_value("any", source) {
+            Some(value)
+        } else {
+            self.error(format!("Invalid dynamic source for {}: {}", name, source), pos);
+            None
+        }
+    }
+
+    fn check_call(&mut self, name: &String, args: &[String], pos: Position) -> Option<&'a Function> {
+        let Some(function) = self.functions.get(name) else {
+            self.error(format!("No such function: {}", name), pos);
+            return None;
+        };
+        if args.len() != function.params.len() {
+            self.error(format!("Function {} takes {} argument(s), got {}", name, function.params.len(), args.len()), pos);
+        }
+        function.params.iter().zip(args).for_each(|((param, typ), arg)| self.check_typed_value(param, typ, arg, pos));
+        Some(function)
+    }
+
+    //Literals have to be of typ, anything else is checked like an assignment
+    fn check_typed_value(&mut self, name: &String, typ: &str, source: &String, pos: Position) {
+        let Some(target) = self.value_type(typ) else {
+            return;
+        };
+        if !self.is_var(source) && function_call(source).is_none() {
+            if literal_value(typ, source).is_some() {
+                return;
+            } else if source == "null" || matches!(target, VariableType::Enum(_, _)) && source.contains("::") {
+                self.check_literal(name, &target, typ.starts_with('?'), source, pos);
+                return;
+            }
+        }
+        if let Some(source) = self.source_type(name, source, pos) {
+            self.check_assign(name, &target, &source, None, pos);
+        }
+    }
+
+    //A primitive or enum type, declared_type reports the invalid ones
+    fn value_type(&self, typ: &str) -> Option<VariableType> {
+        let name = typ.trim_start_matches('?');
+        type_from_name(name).or_else(|| self.enums.contains_key(name).then(|| VariableType::Enum(name.to_string(), None)))
+    }
+
+    fn declared_type(&mut self, typ: &str, pos: Position) -> (Option<VariableType>, bool) {
+        let nullable = typ.starts_with('?');
+        let name = typ.trim_start_matches('?');
+        if let Some(typ) = type_from_name(name) {
+            (Some(typ), nullable)
+        } else if self.enums.contains_key(name) {
+            (Some(VariableType::Enum(name.to_string(), None)), nullable)
+        } else if name == "event" {
+            self.error(String::from("Can't directly initialize event type"), pos);
+            (None, nullable)
+        } else {
+            self.error(format!("Invalid Variable Type: {}", name), pos);
+            (None, nullable)
+        }
+    }
+
+    fn declare(&mut self, name: &String, typ: Option<VariableType>, nullable: bool, pos: Position) {
+        if self.scopes.last().is_some_and(|scope| scope.contains_key(name)) {
+            self.error(format!("Variable {} already exists in this scope", name), pos);
+        } else {
+            if self.warn_shadowing && self.is_var(name) {
+                warning(format!("Variable {} shadows a variable of an outer scope", name), pos);
+            }
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.insert(name.clone(), CheckedVariable {
+                    typ,
+                    nullable
+                });
+            }
+        }
+    }
+
+    fn find(&mut self, name: &String, pos: Position) -> Option<(Option<VariableType>, bool)> {
+        let var = self.lookup(name).map(|var| (var.typ.clone(), var.nullable));
+        if var.is_none() {
+            self.error(format!(r#"Variable "{}" not found in current scope!"#, name), pos);
+        }
+        var
+    }
+
+    fn is_current_event(&self, name: &String) -> bool {
+        name == "this" || name == self.listener
+    }
+
+    //Fields the listener can read from and write to its own event
+    fn current_event_field(&mut self, name: &String, pos: Position) -> Option<VariableType> {
+        let Some(event) = self.registry.get(self.listener) else {
+            if let Some(function) = self.function {
+                self.error(format!("Function {} doesn't handle an event, this can't be used in it", function.name), pos);
+            }
+            return None;
+        };
+        let schema = event.schema();
+        self.output_type(&schema, name, pos)
+    }
+
+    //var.field, var is an event variable or the handled event
+    fn field_type(&mut self, var: &String, field: &String, pos: Position) -> Option<VariableType> {
+        if self.is_current_event(var) {
+            return self.current_event_field(field, pos);
+        }
+        match self.lookup(var).map(|var| var.typ.clone()) {
+            Some(Some(VariableType::Event(event))) => self.output_type(&event.schema(), field, pos),
+            Some(Some(typ)) => {
+                self.error(format!("Variable {} is not an event (type {}), can't read field {}", var, type_to_name(&typ), field), pos);
+                None
+            }
+            Some(None) => None,
+            None => {
+                self.error(format!(r#"Variable "{}" not found in current scope!"#, var), pos);
+                None
+            }
+        }
+    }
+
+    fn output_type(&mut self, schema: &EventSchema, name: &String, pos: Position) -> Option<VariableType> {
+        if let Some(field) = schema.get_output(name) {
+            type_from_name(field.typ.trim_start_matches('?'))
+        } else {
+            let outputs = schema.outputs.iter().map(|field| field.name.clone()).collect::<Vec<String>>();
+            self.error(format!("Event parameter {} not found in event {} (available: {})", name, schema.name, outputs.join(", ")), pos);
+            None
+        }
+    }
+
+    //Innermost scope first, so inner variables shadow outer ones
+    fn lookup(&self, name: &String) -> Option<&CheckedVariable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn is_var(&self, name: &String) -> bool {
+        self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    fn error(&mut self, msg: String, pos: Position) {
+        self.errors.push((msg, pos));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::BuildError;
+    use crate::vm::VM;
+
+    //The checker errors of an OnStart listener with the given body, as "message (at line:index)"
+    fn errors(body: &str) -> Vec<String> {
+        match VM::builder().source(&format!("OnStart {{\n{}}}\n", body)).build() {
+            Ok(_) => vec![],
+            Err(BuildError::Check(errors)) => errors.iter().map(|(msg, pos)| format!("{} (at {})", msg, pos)).collect(),
+            Err(error) => panic!("expected checker errors, found {:?}", error.errors())
+        }
+    }
+
+    //Every error of a whole script, or nothing if it builds
+    fn build_errors(source: &str) -> Vec<String> {
+        VM::builder().source(source).build().err().map(|error| error.to_string().lines().map(String::from).collect()).unwrap_or_default()
+    }
+
+    //An enum with a payload variant, a declared state and the given match arms
+    fn match_errors(arms: &str) -> Vec<String> {
+        build_errors(&format!("@enum {{\n    State {{\n        Idle;\n        Running ticks (u32);\n        Failed message (string);\n    }}\n}}\nOnStart {{\n    state (State) = State::Idle;\n    match state {{\n{}    }}\n}}\n", arms))
+    }
+
+    #[test]
+    fn valid_scripts_pass() {
+        assert!(errors("    x (u8) = 1;\n    y (u16) <= x;\n    #Print <- y;\n").is_empty());
+    }
+
+    #[test]
+    fn type_mismatches() {
+        assert_eq!(errors("    x (u8) = \"a\";\n"), vec!["\"a\" is not of type u8 (at 2:9)"]);
+        assert_eq!(errors("    x (u8) = y;\n"), vec!["y is not of type u8 (at 2:9)"]);
+    }
+
+    #[test]
+    fn rejected_casts() {
+        assert_eq!(errors("    b (bool) = true;\n    c (char) <= b;\n"), vec!["Can't cast bool to char (at 3:12)"]);
+    }
+
+    #[test]
+    fn unknown_events_and_params() {
+        assert_eq!(errors("    #Nope <- 1;\n"), vec!["No such event: Nope (at 2:9)"]);
+        let errors = errors("    #Schedule <- \"OnStart\" <- \"soon\";\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("expected delay (number), found string"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(errors("    #Exit <- 258;\n"), vec!["Exit code 258 is out of range, expected 0 to 255 (at 2:9)"]);
+        assert_eq!(errors("    #Exit <- code: -1;\n"), vec!["Exit code -1 is out of range, expected 0 to 255 (at 2:15)"]);
+        assert_eq!(errors("    #Exit <- 4;\n"), vec!["Exit code 4 is reserved for syntax, type and runtime errors (at 2:9)"]);
+        assert!(errors("    #Exit <- 255;\n").is_empty());
+        assert!(errors("    code (i32) = 256;\n    #Exit <- code;\n").is_empty());
+    }
+
+    #[test]
+    fn redeclarations() {
+        assert_eq!(errors("    x (u8) = 1;\n    x (u8) = 2;\n"), vec!["Variable x already exists in this scope (at 3:9)"]);
+    }
+
+    #[test]
+    fn inner_scopes_shadow_outer_ones() {
+        assert!(errors("    x (u8) = 1;\n    try {\n        x (string) = \"a\";\n        x = \"b\";\n    } catch err {\n    }\n    x = 2;\n").is_empty());
+        assert_eq!(errors("    x (u8) = 1;\n    try {\n        x (string) = \"a\";\n    } catch err {\n    }\n    x = \"b\";\n"), vec!["\"b\" is not of type u8 (at 7:4)"]);
+    }
+
+    #[test]
+    fn non_exhaustive_matches() {
+        assert_eq!(match_errors("        State::Idle {\n        }\n        State::Running ticks {\n        }\n"), vec!["Non-exhaustive match on state, missing: State::Failed (at 10:12)"]);
+        assert!(match_errors("        State::Idle {\n        }\n        State::Running ticks {\n        }\n        State::Failed message {\n        }\n").is_empty());
+    }
+
+    #[test]
+    fn wildcards_make_matches_exhaustive() {
+        assert!(match_errors("        State::Running ticks {\n        }\n        _ {\n        }\n").is_empty());
+        assert_eq!(match_errors("        _ {\n        }\n        State::Idle {\n        }\n"), vec!["Unreachable match arm State::Idle after wildcard (at 13:12)"]);
+    }
+
+    #[test]
+    fn unknown_variants() {
+        assert_eq!(match_errors("        State::Stopped {\n        }\n        _ {\n        }\n"), vec!["Enum State has no variant Stopped (at 11:15)"]);
+        assert_eq!(build_errors("@enum {\n    State {\n        Idle;\n    }\n}\nOnStart {\n    state (State) = State::Stopped;\n}\n"), vec!["Enum State has no variant Stopped (at 7:16)"]);
+    }
+
+    #[test]
+    fn listeners_only_share_globals() {
+        let source = "@memory {\n    count (u8) = 0;\n}\n@custom {\n    Ping {\n    }\n}\nPing {\n    local (u8) <- count;\n}\nOnStart {\n    count = 2;\n    #Print <- local;\n}\n";
+        assert_eq!(build_errors(source), vec!["Invalid dynamic source for Print: local (at 13:10)"]);
+        assert_eq!(build_errors("@memory {\n    count (u8) = \"a\";\n}\n"), vec!["\"a\" is not of type u8 (at 2:13)"]);
+    }
+
+    #[test]
+    fn event_fields_of_the_listener() {
+        let listener = |body: &str| build_errors(&format!("@custom {{\n    Greet {{\n        name (string);\n    }}\n}}\nGreet {{\n{}}}\n", body));
+        assert!(listener("    name (string) <- Greet;\n    this <- name;\n    Greet <- name;\n").is_empty());
+        assert_eq!(listener("    age (u8) <- this;\n"), vec!["Event parameter age not found in event Greet (available: name) (at 7:12)"]);
+        assert_eq!(listener("    name (u8) = 1;\n    this <- name;\n"), vec!["Can't assign to variable name: expected string, found u8 (at 8:8)"]);
+    }
+
+    #[test]
+    fn field_reads() {
+        assert!(errors("    sum (event) <- #+ <- 1, 2;\n    total (i128) <- sum.result;\n    #Print <- sum.result;\n").is_empty());
+        assert_eq!(errors("    sum (event) <- #+ <- 1, 2;\n    total (i128) <- sum.total;\n"), vec!["Event parameter total not found in event + (available: num1, num2, result) (at 3:16)"]);
+        assert_eq!(errors("    x (u8) = 1;\n    y (u8) <- x.value;\n"), vec!["Variable x is not an event (type u8), can't read field value (at 3:10)"]);
+        assert_eq!(errors("    y (u8) <- nothing.value;\n"), vec!["Variable \"nothing\" not found in current scope! (at 2:10)"]);
+    }
+
+    #[test]
+    fn output_bindings_declare_typed_variables() {
+        assert!(errors("    (result -> total) <- #+ <- 2, 3;\n    copy (i128) <- total;\n").is_empty());
+        assert_eq!(errors("    (time) <- #Now;\n    copy (u8) <- time;\n"), vec!["Can't assign to variable copy: expected u8, found u64 (at 3:13)"]);
+        assert_eq!(errors("    total (u8) = 1;\n    (result -> total) <- #+ <- 2, 3;\n"), vec!["Variable total already exists in this scope (at 3:30)"]);
+        assert_eq!(errors("    (total) <- #+ <- 2, 3;\n"), vec!["Event parameter total not found in event + (available: num1, num2, result) (at 2:20)"]);
+    }
+
+    #[test]
+    fn all_errors_are_collected() {
+        assert_eq!(errors("    x (u8) = \"a\";\n    #Nope <- 1;\n").len(), 2);
+    }
+}
@@ -0,0 +1,79 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+//Times are milliseconds since the VM was built
+pub trait Clock {
+    fn now(&self) -> u64;
+    //Blocks (or jumps) until the time is reached, does nothing if it already passed
+    fn wait_until(&mut self, time: u64);
+}
+
+#[derive(Default)]
+pub struct VirtualClock {
+    time: u64
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> u64 {
+        self.time
+    }
+    fn wait_until(&mut self, time: u64) {
+        self.time = self.time.max(time);
+    }
+}
+
+pub struct RealClock {
+    start: Instant
+}
+
+impl RealClock {
+    pub fn new() -> Self {
+        RealClock {
+            start: Instant::now()
+        }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        RealClock::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+    fn wait_until(&mut self, time: u64) {
+        let now = self.now();
+        if time > now {
+            sleep(Duration::from_millis(time - now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::clock::{Clock, RealClock, VirtualClock};
+
+    #[test]
+    fn virtual_clock_jumps_forward() {
+        let mut clock = VirtualClock::default();
+        assert_eq!(clock.now(), 0);
+        clock.wait_until(1_000_000);
+        assert_eq!(clock.now(), 1_000_000);
+        clock.wait_until(10);
+        assert_eq!(clock.now(), 1_000_000);
+    }
+
+    //Never waits, a time that already passed returns right away
+    #[test]
+    fn real_clock_is_monotonic() {
+        let mut clock = RealClock::new();
+        let first = clock.now();
+        clock.wait_until(0);
+        let second = clock.now();
+        assert!(second >= first);
+        assert!(clock.now() >= second);
+    }
+}
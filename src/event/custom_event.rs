@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use crate::event::schema::EventSchema;
+use crate::event::standard_events::Event;
+use crate::util::variables::{type_value_to_string, VariableType};
+
+pub struct CustomEvent {
+    schema: EventSchema,
+    values: HashMap<String, VariableType>
+}
+
+impl CustomEvent {
+    pub fn new(schema: EventSchema) -> Self {
+        CustomEvent {
+            schema,
+            values: HashMap::new()
+        }
+    }
+}
+
+impl Event for CustomEvent {
+    fn name(&self) -> &str {
+        self.schema.name.as_str()
+    }
+    fn schema(&self) -> EventSchema {
+        self.schema.clone()
+    }
+    fn check_param_count(&self, count: u8) -> bool {
+        self.schema.check_param_count(count as usize)
+    }
+    fn get_var(&self, name: &str) -> Option<VariableType> {
+        self.values.get(name).cloned()
+    }
+    fn set_var(&mut self, name: &str, value: VariableType) -> bool {
+        if let Some(field) = self.schema.get_output(name) {
+            if field.accepts(&value) && (field.typ.starts_with('?') || type_value_to_string(&value).is_some()) {
+                self.values.insert(field.name.clone(), value);
+                return true;
+            }
+        }
+        false
+    }
+    fn accept(&mut self, idx: u8, param: VariableType) -> bool {
+        if let Some(field) = self.schema.get_param(idx as usize) {
+            if field.accepts(&param) {
+                self.values.insert(field.name.clone(), param);
+                return true;
+            }
+        }
+        false
+    }
+    fn call(&mut self) {
+    }
+    fn clone_self(&self) -> Box<dyn Event> {
+        Box::new(CustomEvent {
+            schema: self.schema.clone(),
+            values: self.values.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::custom_event::CustomEvent;
+    use crate::event::schema::{EventField, EventSchema};
+    use crate::event::standard_events::Event;
+    use crate::util::variables::{type_value_to_string, VariableType};
+
+    #[test]
+    fn set_var_checks_the_output() {
+        let mut event = CustomEvent::new(EventSchema::new("Move", "").param(EventField::new("x", "i32")).output(EventField::new("x", "i32")).output(EventField::new("label", "?string")));
+        assert!(event.set_var("x", VariableType::I32(Some(3))));
+        assert!(!event.set_var("x", VariableType::U8(Some(3))));
+        assert!(!event.set_var("x", VariableType::I32(None)));
+        assert!(event.set_var("label", VariableType::String(None)));
+        assert!(!event.set_var("y", VariableType::I32(Some(1))));
+        assert_eq!(event.get_var("x").and_then(|x| type_value_to_string(&x)), Some(String::from("3")));
+        assert_eq!(event.clone_self().get_var("x").and_then(|x| type_value_to_string(&x)), Some(String::from("3")));
+    }
+}
@@ -1,10 +1,106 @@
 use std::collections::HashMap;
+use crate::event::standard_events::Event;
 use crate::util::token::Token;
 
+//Returns true to cancel the event for all following listeners
+pub type NativeListener = Box<dyn Fn(&dyn Event) -> bool>;
+
+//Monitors run after all other listeners, even if the event got cancelled, and only see a copy of the event
+pub const MONITOR_PRIORITY: i32 = i32::MIN;
+
 pub struct EventPipeline {
     pub listeners: HashMap<String, Vec<EventListener>>
 }
 
-pub struct EventListener {
-    pub tokens: Vec<Token>
-}
\ No newline at end of file
+pub enum EventListener {
+    Script {
+        tokens: Vec<Token>,
+        priority: i32
+    },
+    Native {
+        callback: NativeListener,
+        priority: i32
+    }
+}
+
+impl EventListener {
+    pub fn priority(&self) -> i32 {
+        match self {
+            EventListener::Script { priority, .. } => *priority,
+            EventListener::Native { priority, .. } => *priority
+        }
+    }
+}
+
+pub fn priority_from_name(name: &str) -> Option<i32> {
+    match name {
+        "lowest" => Some(-200),
+        "low" => Some(-100),
+        "normal" => Some(0),
+        "high" => Some(100),
+        "highest" => Some(200),
+        "monitor" => Some(MONITOR_PRIORITY),
+        _ => name.parse::<i32>().ok().filter(|priority| *priority != MONITOR_PRIORITY)
+    }
+}
+
+impl EventPipeline {
+    //Higher priorities run first, listeners with the same priority keep their insertion order
+    pub fn add(&mut self, name: &str, listener: EventListener) {
+        let listeners = self.listeners.entry(name.to_string()).or_default();
+        let idx = listeners.iter().position(|other| other.priority() < listener.priority()).unwrap_or(listeners.len());
+        listeners.insert(idx, listener);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::event_pipeline::{priority_from_name, EventListener, EventPipeline, MONITOR_PRIORITY};
+
+    fn script(priority: i32) -> EventListener {
+        EventListener::Script {
+            tokens: vec![],
+            priority
+        }
+    }
+
+    fn native(priority: i32) -> EventListener {
+        EventListener::Native {
+            callback: Box::new(|_| false),
+            priority
+        }
+    }
+
+    //(kind, priority) of every listener of the event, in the order they run
+    fn order(pipeline: &EventPipeline, name: &str) -> Vec<(&'static str, i32)> {
+        pipeline.listeners[name].iter().map(|listener| match listener {
+            EventListener::Script { priority, .. } => ("script", *priority),
+            EventListener::Native { priority, .. } => ("native", *priority)
+        }).collect()
+    }
+
+    #[test]
+    fn higher_priorities_run_first() {
+        let mut pipeline = EventPipeline {
+            listeners: Default::default()
+        };
+        pipeline.add("Ping", script(0));
+        pipeline.add("Ping", native(MONITOR_PRIORITY));
+        pipeline.add("Ping", native(-5));
+        pipeline.add("Ping", script(100));
+        pipeline.add("Ping", native(0));
+        pipeline.add("Pong", native(1));
+        assert_eq!(order(&pipeline, "Ping"), vec![("script", 100), ("script", 0), ("native", 0), ("native", -5), ("native", MONITOR_PRIORITY)]);
+        assert_eq!(order(&pipeline, "Pong"), vec![("native", 1)]);
+    }
+
+    #[test]
+    fn priority_names() {
+        assert_eq!(priority_from_name("lowest"), Some(-200));
+        assert_eq!(priority_from_name("high"), Some(100));
+        assert_eq!(priority_from_name("-5"), Some(-5));
+        assert_eq!(priority_from_name("monitor"), Some(MONITOR_PRIORITY));
+        assert_eq!(priority_from_name(&MONITOR_PRIORITY.to_string()), None);
+        assert_eq!(priority_from_name("urgent"), None);
+    }
+}
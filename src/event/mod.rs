@@ -1,2 +1,7 @@
 pub mod standard_events;
-pub mod event_pipeline;
\ No newline at end of file
+pub mod event_pipeline;
+pub mod schema;
+pub mod custom_event;
+pub mod registry;
+pub mod scheduler;
+pub mod clock;
\ No newline at end of file
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use crate::event::custom_event::CustomEvent;
+use crate::event::scheduler::SharedScheduler;
+use crate::event::schema::EventSchema;
+use crate::event::standard_events::{Event, register_standard_events};
+
+pub type EventFactory = Box<dyn Fn() -> Box<dyn Event>>;
+
+//Events are registered by their full name, namespaced events use "::" as a separator (net::Send)
+pub struct EventRegistry {
+    factories: HashMap<String, EventFactory>,
+    names: Vec<String>
+}
+
+impl EventRegistry {
+    pub fn new(scheduler: &SharedScheduler) -> Self {
+        let mut registry = EventRegistry::empty();
+        register_standard_events(&mut registry, scheduler);
+        registry
+    }
+
+    pub fn empty() -> Self {
+        EventRegistry {
+            factories: HashMap::new(),
+            names: vec![]
+        }
+    }
+
+    pub fn register<F: Fn() -> Box<dyn Event> + 'static>(&mut self, name: &str, factory: F) -> Result<(), String> {
+        if name.is_empty() || name.split("::").any(|part| part.is_empty() || part.contains(char::is_whitespace)) {
+            return Err(format!("Invalid event name {}", name));
+        }
+        if self.factories.contains_key(name) {
+            return Err(format!("Event {} is already registered", name));
+        }
+        self.factories.insert(name.to_string(), Box::new(factory));
+        self.names.push(name.to_string());
+        Ok(())
+    }
+
+    pub fn register_namespaced<F: Fn() -> Box<dyn Event> + 'static>(&mut self, namespace: &str, name: &str, factory: F) -> Result<(), String> {
+        self.register(format!("{}::{}", namespace, name).as_str(), factory)
+    }
+
+    pub fn register_custom(&mut self, schema: EventSchema) -> Result<(), String> {
+        let name = schema.name.clone();
+        self.register(name.as_str(), move || Box::new(CustomEvent::new(schema.clone())))
+    }
+
+    pub fn get(&self, name: &String) -> Option<Box<dyn Event>> {
+        self.factories.get(name).map(|factory| factory())
+    }
+
+    pub fn contains(&self, name: &String) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    //Registration order, used for the docs
+    pub fn names(&self) -> &Vec<String> {
+        &self.names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::registry::EventRegistry;
+    use crate::event::scheduler::Scheduler;
+    use crate::event::schema::{EventField, EventSchema};
+    use crate::event::standard_events::OnStart;
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let mut registry = EventRegistry::empty();
+        assert!(registry.register("Ping", || Box::new(OnStart)).is_ok());
+        assert_eq!(registry.register("Ping", || Box::new(OnStart)), Err(String::from("Event Ping is already registered")));
+        assert_eq!(registry.register_custom(EventSchema::new("Ping", "")), Err(String::from("Event Ping is already registered")));
+        assert_eq!(registry.names(), &vec![String::from("Ping")]);
+    }
+
+    #[test]
+    fn standard_events_can_not_be_replaced() {
+        let mut registry = EventRegistry::new(&Scheduler::shared());
+        assert!(registry.contains(&String::from("Print")));
+        assert!(registry.register_custom(EventSchema::new("Print", "")).is_err());
+        assert_eq!(registry.get(&String::from("Print")).map(|event| event.schema().description), Some(String::from("Prints all parameters joined together as one line")));
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let mut registry = EventRegistry::empty();
+        for name in ["", "net::", "::Send", "net::::Send", "On Start"] {
+            assert_eq!(registry.register(name, || Box::new(OnStart)), Err(format!("Invalid event name {}", name)));
+        }
+        assert!(registry.names().is_empty());
+    }
+
+    #[test]
+    fn namespaced_and_custom_events() {
+        let mut registry = EventRegistry::empty();
+        registry.register_namespaced("net", "Send", || Box::new(OnStart)).unwrap();
+        registry.register_custom(EventSchema::new("Move", "").param(EventField::new("x", "number"))).unwrap();
+        assert!(registry.contains(&String::from("net::Send")));
+        assert!(!registry.contains(&String::from("Send")));
+        assert_eq!(registry.names(), &vec![String::from("net::Send"), String::from("Move")]);
+
+        //Every get is a new instance
+        let mut first = registry.get(&String::from("Move")).unwrap();
+        assert!(first.accept(0, 1.into()));
+        assert!(first.get_var("x").is_some());
+        assert!(registry.get(&String::from("Move")).unwrap().get_var("x").is_none());
+        assert!(registry.get(&String::from("Nope")).is_none());
+    }
+}
@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::event::clock::{Clock, VirtualClock};
+
+pub type SharedScheduler = Rc<RefCell<Scheduler>>;
+
+#[derive(Clone, Debug)]
+pub struct Timer {
+    pub id: u64,
+    pub event: String,
+    pub due: u64,
+    pub interval: Option<u64>
+}
+
+pub struct Scheduler {
+    clock: Box<dyn Clock>,
+    next_id: u64,
+    timers: Vec<Timer>,
+    exit_code: Option<i32>
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            clock: Box::new(VirtualClock::default()),
+            next_id: 0,
+            timers: vec![],
+            exit_code: None
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn shared() -> SharedScheduler {
+        Rc::new(RefCell::new(Scheduler::default()))
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    pub fn wait_until(&mut self, time: u64) {
+        self.clock.wait_until(time);
+    }
+
+    pub fn schedule(&mut self, event: String, delay: u64, repeat: bool) -> u64 {
+        self.next_id += 1;
+        if self.exit_code.is_some() {
+            return self.next_id;
+        }
+        self.timers.push(Timer {
+            id: self.next_id,
+            event,
+            due: self.now() + delay,
+            interval: if repeat { Some(delay) } else { None }
+        });
+        self.next_id
+    }
+
+    pub fn cancel(&mut self, id: u64) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|timer| timer.id != id);
+        len != self.timers.len()
+    }
+
+    //Drops every timer, the first requested code wins
+    pub fn request_exit(&mut self, code: i32) {
+        if self.exit_code.is_none() {
+            self.exit_code = Some(code);
+            self.timers.clear();
+        }
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    pub fn next_due(&self) -> Option<u64> {
+        self.timers.iter().map(|timer| timer.due).min()
+    }
+
+    //Timers due at the same time fire in the order they were scheduled, repeating timers are scheduled again
+    pub fn pop_due(&mut self, until: u64) -> Option<Timer> {
+        let idx = self.timers.iter().enumerate()
+            .filter(|(_, timer)| timer.due <= until)
+            .min_by_key(|(_, timer)| (timer.due, timer.id))
+            .map(|(idx, _)| idx)?;
+        let timer = self.timers.remove(idx);
+        if let Some(interval) = timer.interval {
+            self.timers.push(Timer {
+                due: timer.due + interval,
+                ..timer.clone()
+            });
+        }
+        Some(timer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::scheduler::Scheduler;
+
+    //Runs the timers like the simulation does, returns the (time, event) pairs that fired
+    fn run(scheduler: &mut Scheduler, until: u64) -> Vec<(u64, String)> {
+        let mut fired = vec![];
+        while let Some(due) = scheduler.next_due().filter(|due| *due <= until) {
+            scheduler.wait_until(due);
+            let timer = scheduler.pop_due(scheduler.now()).unwrap();
+            fired.push((scheduler.now(), timer.event));
+        }
+        fired
+    }
+
+    #[test]
+    fn timers_fire_in_order() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(String::from("C"), 20, false);
+        scheduler.schedule(String::from("A"), 10, false);
+        scheduler.schedule(String::from("B"), 10, false);
+        assert_eq!(scheduler.next_due(), Some(10));
+        assert_eq!(run(&mut scheduler, 100), vec![(10, String::from("A")), (10, String::from("B")), (20, String::from("C"))]);
+        assert_eq!(scheduler.next_due(), None);
+    }
+
+    #[test]
+    fn pop_due_respects_until() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(String::from("A"), 10, false);
+        assert!(scheduler.pop_due(9).is_none());
+        assert_eq!(scheduler.pop_due(10).map(|timer| timer.event), Some(String::from("A")));
+        assert!(scheduler.pop_due(10).is_none());
+    }
+
+    #[test]
+    fn delays_are_relative_to_now() {
+        let mut scheduler = Scheduler::default();
+        scheduler.wait_until(50);
+        scheduler.schedule(String::from("A"), 10, false);
+        assert_eq!(scheduler.next_due(), Some(60));
+    }
+
+    #[test]
+    fn repeating_timers() {
+        let mut scheduler = Scheduler::default();
+        let id = scheduler.schedule(String::from("Tick"), 10, true);
+        scheduler.schedule(String::from("Once"), 15, false);
+        assert_eq!(run(&mut scheduler, 35), vec![(10, String::from("Tick")), (15, String::from("Once")), (20, String::from("Tick")), (30, String::from("Tick"))]);
+        assert_eq!(scheduler.next_due(), Some(40));
+        assert!(scheduler.cancel(id));
+        assert_eq!(scheduler.next_due(), None);
+    }
+
+    #[test]
+    fn cancel() {
+        let mut scheduler = Scheduler::default();
+        let first = scheduler.schedule(String::from("A"), 10, false);
+        let second = scheduler.schedule(String::from("B"), 20, false);
+        assert_ne!(first, second);
+        assert!(scheduler.cancel(first));
+        assert!(!scheduler.cancel(first));
+        assert_eq!(run(&mut scheduler, 100), vec![(20, String::from("B"))]);
+        assert!(!scheduler.cancel(second));
+    }
+
+    #[test]
+    fn exit() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(String::from("A"), 10, true);
+        assert_eq!(scheduler.exit_code(), None);
+        scheduler.request_exit(5);
+        scheduler.request_exit(6);
+        assert_eq!(scheduler.exit_code(), Some(5));
+        assert_eq!(scheduler.next_due(), None);
+        //Timers scheduled after the exit never fire, but still get an id
+        let id = scheduler.schedule(String::from("B"), 0, false);
+        assert!(id > 1);
+        assert!(run(&mut scheduler, 100).is_empty());
+    }
+}
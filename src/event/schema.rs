@@ -0,0 +1,239 @@
+use crate::util::token::EventParam;
+use crate::util::variables::{fits_number, type_to_name, VariableType};
+
+#[derive(Clone, Debug)]
+pub struct EventSchema {
+    pub name: String,
+    pub description: String,
+    pub params: Vec<EventField>,
+    pub outputs: Vec<EventField>,
+    pub variadic: bool
+}
+
+#[derive(Clone, Debug)]
+pub struct EventField {
+    pub name: String,
+    pub typ: String,
+    pub optional: bool,
+    pub default: Option<String>
+}
+
+impl EventField {
+    pub fn new(name: &str, typ: &str) -> Self {
+        EventField {
+            name: name.to_string(),
+            typ: typ.to_string(),
+            optional: false,
+            default: None
+        }
+    }
+
+    pub fn optional(name: &str, typ: &str) -> Self {
+        EventField {
+            name: name.to_string(),
+            typ: typ.to_string(),
+            optional: true,
+            default: None
+        }
+    }
+
+    pub fn with_default(name: &str, typ: &str, default: &str) -> Self {
+        EventField {
+            name: name.to_string(),
+            typ: typ.to_string(),
+            optional: true,
+            default: Some(default.to_string())
+        }
+    }
+
+    //"any" and "number" (every integer type) are accepted next to the regular type names
+    pub fn accepts(&self, value: &VariableType) -> bool {
+        match self.typ.trim_start_matches('?') {
+            "any" => true,
+            "number" => fits_number(value),
+            typ => type_to_name(value) == typ
+        }
+    }
+}
+
+impl EventSchema {
+    pub fn new(name: &str, description: &str) -> Self {
+        EventSchema {
+            name: name.to_string(),
+            description: description.to_string(),
+            params: vec![],
+            outputs: vec![],
+            variadic: false
+        }
+    }
+
+    pub fn param(mut self, field: EventField) -> Self {
+        self.params.push(field);
+        self
+    }
+
+    pub fn output(mut self, field: EventField) -> Self {
+        self.outputs.push(field);
+        self
+    }
+
+    pub fn variadic(mut self) -> Self {
+        self.variadic = true;
+        self
+    }
+
+    pub fn get_param(&self, idx: usize) -> Option<&EventField> {
+        if self.variadic && idx >= self.params.len() {
+            self.params.last()
+        } else {
+            self.params.get(idx)
+        }
+    }
+
+    pub fn get_output(&self, name: &str) -> Option<&EventField> {
+        self.outputs.iter().find(|field| field.name == name)
+    }
+
+    pub fn check_param_count(&self, count: usize) -> bool {
+        let required = self.params.iter().filter(|field| !field.optional).count();
+        count >= required && (self.variadic || count <= self.params.len())
+    }
+
+    //Orders named params by their declaration and fills in defaults, the result is positional
+    pub fn bind(&self, params: &[EventParam]) -> Result<Vec<String>, String> {
+        let mut bound: Vec<Option<String>> = vec![None; self.params.len()];
+        let mut named = false;
+        for (idx, param) in params.iter().enumerate() {
+            if let Some(name) = &param.name {
+                named = true;
+                if let Some(field_idx) = self.params.iter().position(|field| field.name == *name) {
+                    if bound[field_idx].is_some() {
+                        return Err(format!("Parameter {} of event {} is given twice", name, self.name));
+                    }
+                    bound[field_idx] = Some(param.value.clone());
+                } else {
+                    let names = self.params.iter().map(|field| field.name.clone()).collect::<Vec<String>>();
+                    return Err(format!("Event {} has no parameter {} (expected one of: {})", self.name, name, names.join(", ")));
+                }
+            } else if named {
+                return Err(format!("Positional parameter {} after named parameters for event {}", param.value, self.name));
+            } else if idx < bound.len() {
+                bound[idx] = Some(param.value.clone());
+            } else {
+                bound.push(Some(param.value.clone()));
+            }
+        }
+
+        let mut values = vec![];
+        for (idx, value) in bound.into_iter().enumerate() {
+            if let Some(value) = value.or_else(|| self.params.get(idx).and_then(|field| field.default.clone())) {
+                if values.len() < idx {
+                    return Err(format!("Missing parameter {} for event {}", self.params[values.len()].name, self.name));
+                }
+                values.push(value);
+            } else if let Some(field) = self.params.get(idx).filter(|field| !field.optional) {
+                return Err(format!("Missing parameter {} for event {}", field.name, self.name));
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn signature(&self) -> String {
+        let mut params = self.params.iter().map(field_to_string).collect::<Vec<String>>();
+        if self.variadic {
+            params.push(String::from("..."));
+        }
+        if params.is_empty() {
+            format!("#{}", self.name)
+        } else {
+            format!("#{} <- {}", self.name, params.join(", "))
+        }
+    }
+
+    pub fn documentation(&self) -> String {
+        let mut doc = format!("{}\n    {}", self.signature(), self.description);
+        if !self.outputs.is_empty() {
+            let outputs = self.outputs.iter().map(field_to_string).collect::<Vec<String>>();
+            doc.push_str(format!("\n    outputs: {}", outputs.join(", ")).as_str());
+        }
+        doc
+    }
+}
+
+fn field_to_string(field: &EventField) -> String {
+    if let Some(default) = &field.default {
+        format!("[{} ({}) = {}]", field.name, field.typ, default)
+    } else if field.optional {
+        format!("[{} ({})]", field.name, field.typ)
+    } else {
+        format!("{} ({})", field.name, field.typ)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::schema::{EventField, EventSchema};
+    use crate::util::token::EventParam;
+
+    fn schema() -> EventSchema {
+        EventSchema::new("Move", "Moves something")
+            .param(EventField::new("x", "number"))
+            .param(EventField::new("y", "number"))
+            .param(EventField::with_default("speed", "number", "1"))
+            .param(EventField::optional("label", "string"))
+    }
+
+    fn positional(value: &str) -> EventParam {
+        EventParam { name: None, value: value.to_string() }
+    }
+
+    fn named(name: &str, value: &str) -> EventParam {
+        EventParam { name: Some(name.to_string()), value: value.to_string() }
+    }
+
+    #[test]
+    fn positional_params() {
+        assert_eq!(schema().bind(&[positional("1"), positional("2"), positional("3"), positional("\"a\"")]), Ok(vec![String::from("1"), String::from("2"), String::from("3"), String::from("\"a\"")]));
+    }
+
+    #[test]
+    fn named_params_follow_the_declaration_order() {
+        assert_eq!(schema().bind(&[named("y", "2"), named("speed", "5"), named("x", "1")]), Ok(vec![String::from("1"), String::from("2"), String::from("5")]));
+        assert_eq!(schema().bind(&[positional("1"), named("y", "2")]), Ok(vec![String::from("1"), String::from("2"), String::from("1")]));
+    }
+
+    #[test]
+    fn defaults_are_filled_in() {
+        assert_eq!(schema().bind(&[positional("1"), positional("2")]), Ok(vec![String::from("1"), String::from("2"), String::from("1")]));
+        assert_eq!(schema().bind(&[named("x", "1"), named("y", "2"), named("label", "\"a\"")]), Ok(vec![String::from("1"), String::from("2"), String::from("1"), String::from("\"a\"")]));
+    }
+
+    #[test]
+    fn positional_after_named_is_rejected() {
+        let error = schema().bind(&[named("x", "1"), positional("2")]).unwrap_err();
+        assert!(error.contains("Positional parameter 2 after named parameters"), "{}", error);
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let error = schema().bind(&[named("z", "1")]).unwrap_err();
+        assert!(error.contains("has no parameter z (expected one of: x, y, speed, label)"), "{}", error);
+        let error = schema().bind(&[positional("1"), named("x", "2")]).unwrap_err();
+        assert!(error.contains("given twice"), "{}", error);
+    }
+
+    #[test]
+    fn missing_params_are_rejected() {
+        assert_eq!(schema().bind(&[named("y", "2")]), Err(String::from("Missing parameter x for event Move")));
+        let schema = EventSchema::new("Tag", "").param(EventField::optional("a", "any")).param(EventField::new("b", "any"));
+        assert!(schema.bind(&[named("b", "1")]).is_err());
+    }
+
+    #[test]
+    fn variadic_params() {
+        let schema = EventSchema::new("Print", "").param(EventField::new("values", "any")).variadic();
+        assert_eq!(schema.bind(&[positional("1"), positional("2"), positional("3")]).map(|values| values.len()), Ok(3));
+        assert!(schema.check_param_count(3));
+        assert!(!schema.check_param_count(0));
+    }
+}
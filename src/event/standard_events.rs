@@ -1,18 +1,33 @@
 use std::fmt::{Debug, Formatter};
+use crate::event::registry::EventRegistry;
+use crate::event::scheduler::SharedScheduler;
+use crate::event::schema::{EventField, EventSchema};
 use crate::util::debug::debug_str;
-use crate::util::variables::{type_value_to_number, type_value_to_string, VariableType};
+use crate::util::{runtime_error_unknown_pos, RuntimeError};
+use event_derive::Event;
+use crate::util::variables::{type_value_to_string, VariableType};
+use crate::vm::{PARSE_ERROR_EXIT, RUNTIME_ERROR_EXIT, TYPE_ERROR_EXIT};
 
-pub fn get_event(name: &String) -> Option<Box<dyn Event>> {
-    match name.as_str() {
-        "OnStart" => Some(Box::new(OnStart)),
-        "Print" => Some(Box::new(Print::default())),
-        "+" => Some(Box::new(MathAdd::default())),
-        "-" => Some(Box::new(MathSubtract::default())),
-        "*" => Some(Box::new(MathMultiply::default())),
-        "/" => Some(Box::new(MathDivide::default())),
-        "%" => Some(Box::new(MathModulo::default())),
-        _ => None
-    }
+pub fn register_standard_events(registry: &mut EventRegistry, scheduler: &SharedScheduler) {
+    registry.register("OnStart", || Box::new(OnStart)).unwrap();
+    registry.register("Print", || Box::new(Print::default())).unwrap();
+    registry.register("OnError", || Box::new(OnError::default())).unwrap();
+    registry.register("OnExit", || Box::new(OnExit::default())).unwrap();
+    registry.register("+", || Box::new(MathAdd::default())).unwrap();
+    registry.register("-", || Box::new(MathSubtract::default())).unwrap();
+    registry.register("*", || Box::new(MathMultiply::default())).unwrap();
+    registry.register("/", || Box::new(MathDivide::default())).unwrap();
+    registry.register("%", || Box::new(MathModulo::default())).unwrap();
+    let schedule = scheduler.clone();
+    registry.register("Schedule", move || Box::new(Schedule { scheduler: schedule.clone(), ..Default::default() })).unwrap();
+    let cancel = scheduler.clone();
+    registry.register("Cancel", move || Box::new(Cancel { scheduler: cancel.clone(), ..Default::default() })).unwrap();
+    let tick = scheduler.clone();
+    registry.register("Tick", move || Box::new(Tick { scheduler: tick.clone(), ..Default::default() })).unwrap();
+    let exit = scheduler.clone();
+    registry.register("Exit", move || Box::new(Exit { scheduler: exit.clone(), ..Default::default() })).unwrap();
+    let now = scheduler.clone();
+    registry.register("Now", move || Box::new(Now { scheduler: now.clone(), ..Default::default() })).unwrap();
 }
 
 impl Debug for dyn Event {
@@ -29,35 +44,55 @@ impl Clone for Box<dyn Event> {
 
 pub trait Event {
     fn name(&self) -> &str;
+    fn schema(&self) -> EventSchema;
     fn check_param_count(&self, count: u8) -> bool;
-    fn get_var(&self, name: &String) -> Option<VariableType>;
+    fn get_var(&self, name: &str) -> Option<VariableType>;
+    //Sets an output field, false if there is no such field or the value has the wrong type
+    fn set_var(&mut self, name: &str, value: VariableType) -> bool;
     fn accept(&mut self, idx: u8, param: VariableType) -> bool;
     fn call(&mut self);
     fn clone_self(&self) -> Box<dyn Event>;
 }
 
+#[derive(Event)]
+#[event(name = "OnStart", description = "Called once when the script starts", call = "start")]
 pub struct OnStart;
-impl Event for OnStart {
-    fn name(&self) -> &str {
-        "OnStart"
-    }
-    fn check_param_count(&self, count: u8) -> bool {
-        count == 0
-    }
-    fn accept(&mut self, _idx: u8, _param: VariableType) -> bool {
-        false
-    }
-    fn get_var(&self, _name: &String) -> Option<VariableType> {
-        None
-    }
-    fn call(&mut self) {
+impl OnStart {
+    fn start(&mut self) {
         debug_str("START EVENT CALLED");
     }
-    fn clone_self(&self) -> Box<dyn Event> {
-        Box::new(OnStart)
+}
+
+#[derive(Event, Default)]
+#[event(name = "OnError", description = "Fired for runtime errors no try block caught, catch blocks get the error as this event")]
+pub struct OnError {
+    #[param]
+    #[output]
+    message: Option<String>,
+    #[param(default = "1")]
+    #[output]
+    code: Option<i32>,
+    #[output]
+    position: Option<String>
+}
+impl OnError {
+    pub fn from_error(error: &RuntimeError) -> Self {
+        OnError {
+            message: Some(error.message.clone()),
+            code: Some(error.code),
+            position: Some(error.pos.map(|pos| pos.to_string()).unwrap_or(String::from("unknown")))
+        }
     }
 }
 
+#[derive(Event, Default)]
+#[event(name = "OnExit", description = "Fired once when the VM shuts down, after everything scheduled has run")]
+pub struct OnExit {
+    #[param(default = "0")]
+    #[output]
+    code: Option<i32>
+}
+
 #[derive(Default)]
 pub struct Print {
     message: Option<String>
@@ -66,6 +101,12 @@ impl Event for Print {
     fn name(&self) -> &str {
         "Print"
     }
+    fn schema(&self) -> EventSchema {
+        EventSchema::new("Print", "Prints all parameters joined together as one line")
+            .param(EventField::new("message", "any"))
+            .variadic()
+            .output(EventField::new("message", "?string"))
+    }
     fn check_param_count(&self, count: u8) -> bool {
         count > 0
     }
@@ -81,12 +122,21 @@ impl Event for Print {
         }
         true
     }
-    fn get_var(&self, name: &String) -> Option<VariableType> {
-        match name.as_str() {
+    fn get_var(&self, name: &str) -> Option<VariableType> {
+        match name {
             "message" => Some(VariableType::String(self.message.clone())),
             _ => None
         }
     }
+    fn set_var(&mut self, name: &str, value: VariableType) -> bool {
+        match (name, value) {
+            ("message", VariableType::String(message)) => {
+                self.message = message;
+                true
+            }
+            _ => false
+        }
+    }
     fn call(&mut self) {
         if let Some(message) = &self.message {
             println!("{}", message);
@@ -101,252 +151,292 @@ impl Event for Print {
     }
 }
 
-#[derive(Default)]
+#[derive(Event, Default)]
+#[event(name = "+", description = "Adds num2 to num1", call = "add")]
 pub struct MathAdd {
+    #[param(typ = "number")]
+    #[output]
     num1: Option<i128>,
+    #[param(typ = "number")]
+    #[output]
     num2: Option<i128>,
+    #[output]
     result: Option<i128>
 }
-impl Event for MathAdd {
-    fn name(&self) -> &str {
-        "+"
-    }
-    fn check_param_count(&self, count: u8) -> bool {
-        count == 2
-    }
-    fn get_var(&self, name: &String) -> Option<VariableType> {
-        match name.as_str() {
-            "num1" => Some(VariableType::I128(self.num1.clone())),
-            "num2" => Some(VariableType::I128(self.num2.clone())),
-            "result" => Some(VariableType::I128(self.result.clone())),
-            _ => None
-        }
-    }
-    fn accept(&mut self, idx: u8, param: VariableType) -> bool {
-        match idx {
-            0 => {
-                self.num1 = type_value_to_number(&param);
-                true
-            },
-            1 => {
-                self.num2 = type_value_to_number(&param);
-                true
-            },
-            _ => false
-        }
-    }
-    fn call(&mut self) {
+impl MathAdd {
+    fn add(&mut self) {
         if let Some(num1) = &self.num1 {
             if let Some(num2) = &self.num2 {
                 self.result = Some(num1 + num2);
             }
         }
     }
-    fn clone_self(&self) -> Box<dyn Event> {
-        Box::new(MathAdd {
-            num1: self.num1,
-            num2: self.num2,
-            result: self.result
-        })
-    }
 }
 
-#[derive(Default)]
+#[derive(Event, Default)]
+#[event(name = "-", description = "Subtracts num2 from num1", call = "subtract")]
 pub struct MathSubtract {
+    #[param(typ = "number")]
+    #[output]
     num1: Option<i128>,
+    #[param(typ = "number")]
+    #[output]
     num2: Option<i128>,
+    #[output]
     result: Option<i128>
 }
-impl Event for MathSubtract {
-    fn name(&self) -> &str {
-        "-"
-    }
-    fn check_param_count(&self, count: u8) -> bool {
-        count == 2
-    }
-    fn get_var(&self, name: &String) -> Option<VariableType> {
-        match name.as_str() {
-            "num1" => Some(VariableType::I128(self.num1.clone())),
-            "num2" => Some(VariableType::I128(self.num2.clone())),
-            "result" => Some(VariableType::I128(self.result.clone())),
-            _ => None
-        }
-    }
-    fn accept(&mut self, idx: u8, param: VariableType) -> bool {
-        match idx {
-            0 => {
-                self.num1 = type_value_to_number(&param);
-                true
-            },
-            1 => {
-                self.num2 = type_value_to_number(&param);
-                true
-            },
-            _ => false
-        }
-    }
-    fn call(&mut self) {
+impl MathSubtract {
+    fn subtract(&mut self) {
         if let Some(num1) = &self.num1 {
             if let Some(num2) = &self.num2 {
                 self.result = Some(num1 - num2);
             }
         }
     }
-    fn clone_self(&self) -> Box<dyn Event> {
-        Box::new(MathAdd {
-            num1: self.num1,
-            num2: self.num2,
-            result: self.result
-        })
-    }
 }
 
-#[derive(Default)]
+#[derive(Event, Default)]
+#[event(name = "*", description = "Multiplies num1 with num2", call = "multiply")]
 pub struct MathMultiply {
+    #[param(typ = "number")]
+    #[output]
     num1: Option<i128>,
+    #[param(typ = "number")]
+    #[output]
     num2: Option<i128>,
+    #[output]
     result: Option<i128>
 }
-impl Event for MathMultiply {
-    fn name(&self) -> &str {
-        "*"
-    }
-    fn check_param_count(&self, count: u8) -> bool {
-        count == 2
-    }
-    fn get_var(&self, name: &String) -> Option<VariableType> {
-        match name.as_str() {
-            "num1" => Some(VariableType::I128(self.num1.clone())),
-            "num2" => Some(VariableType::I128(self.num2.clone())),
-            "result" => Some(VariableType::I128(self.result.clone())),
-            _ => None
-        }
-    }
-    fn accept(&mut self, idx: u8, param: VariableType) -> bool {
-        match idx {
-            0 => {
-                self.num1 = type_value_to_number(&param);
-                true
-            },
-            1 => {
-                self.num2 = type_value_to_number(&param);
-                true
-            },
-            _ => false
-        }
-    }
-    fn call(&mut self) {
+impl MathMultiply {
+    fn multiply(&mut self) {
         if let Some(num1) = &self.num1 {
             if let Some(num2) = &self.num2 {
                 self.result = Some(num1 * num2);
             }
         }
     }
-    fn clone_self(&self) -> Box<dyn Event> {
-        Box::new(MathAdd {
-            num1: self.num1,
-            num2: self.num2,
-            result: self.result
-        })
-    }
 }
 
-#[derive(Default)]
+#[derive(Event, Default)]
+#[event(name = "/", description = "Divides num1 by num2", call = "divide")]
 pub struct MathDivide {
+    #[param(typ = "number")]
+    #[output]
     num1: Option<i128>,
+    #[param(typ = "number")]
+    #[output]
     num2: Option<i128>,
+    #[output]
     result: Option<i128>
 }
-impl Event for MathDivide {
-    fn name(&self) -> &str {
-        "/"
-    }
-    fn check_param_count(&self, count: u8) -> bool {
-        count == 2
-    }
-    fn get_var(&self, name: &String) -> Option<VariableType> {
-        match name.as_str() {
-            "num1" => Some(VariableType::I128(self.num1.clone())),
-            "num2" => Some(VariableType::I128(self.num2.clone())),
-            "result" => Some(VariableType::I128(self.result.clone())),
-            _ => None
-        }
-    }
-    fn accept(&mut self, idx: u8, param: VariableType) -> bool {
-        match idx {
-            0 => {
-                self.num1 = type_value_to_number(&param);
-                true
-            },
-            1 => {
-                self.num2 = type_value_to_number(&param);
-                true
-            },
-            _ => false
-        }
-    }
-    fn call(&mut self) {
+impl MathDivide {
+    fn divide(&mut self) {
         if let Some(num1) = &self.num1 {
             if let Some(num2) = &self.num2 {
                 self.result = Some(num1 / num2);
             }
         }
     }
-    fn clone_self(&self) -> Box<dyn Event> {
-        Box::new(MathAdd {
-            num1: self.num1,
-            num2: self.num2,
-            result: self.result
-        })
-    }
 }
 
-#[derive(Default)]
+#[derive(Event, Default)]
+#[event(name = "%", description = "Remainder of dividing num1 by num2", call = "modulo")]
 pub struct MathModulo {
+    #[param(typ = "number")]
+    #[output]
     num1: Option<i128>,
+    #[param(typ = "number")]
+    #[output]
     num2: Option<i128>,
+    #[output]
     result: Option<i128>
 }
-impl Event for MathModulo {
-    fn name(&self) -> &str {
-        "%"
+impl MathModulo {
+    fn modulo(&mut self) {
+        if let Some(num1) = &self.num1 {
+            if let Some(num2) = &self.num2 {
+                self.result = Some(num1 % num2);
+            }
+        }
     }
-    fn check_param_count(&self, count: u8) -> bool {
-        count == 2
+}
+
+#[derive(Event, Default)]
+#[event(name = "Schedule", description = "Fires the event after delay milliseconds, and every delay milliseconds after that if repeat is set", call = "schedule")]
+pub struct Schedule {
+    scheduler: SharedScheduler,
+    #[param]
+    #[output]
+    event: Option<String>,
+    #[param(typ = "number")]
+    #[output]
+    delay: Option<i128>,
+    #[param(default = "false")]
+    #[output]
+    repeat: Option<bool>,
+    #[output]
+    id: Option<u64>
+}
+impl Schedule {
+    fn schedule(&mut self) {
+        if let Some(event) = &self.event {
+            if let Some(delay) = self.delay {
+                let repeat = self.repeat.unwrap_or(false);
+                if delay < 0 || delay > u64::MAX as i128 {
+                    runtime_error_unknown_pos(format!("Invalid delay {} for scheduled event {}", delay, event));
+                }
+                if repeat && delay == 0 {
+                    runtime_error_unknown_pos(format!("Repeating event {} needs a delay above 0", event));
+                }
+                self.id = Some(self.scheduler.borrow_mut().schedule(event.clone(), delay as u64, repeat));
+            }
+        }
     }
-    fn get_var(&self, name: &String) -> Option<VariableType> {
-        match name.as_str() {
-            "num1" => Some(VariableType::I128(self.num1.clone())),
-            "num2" => Some(VariableType::I128(self.num2.clone())),
-            "result" => Some(VariableType::I128(self.result.clone())),
-            _ => None
+}
+
+#[derive(Event, Default)]
+#[event(name = "Cancel", description = "Cancels a scheduled event by the id Schedule returned", call = "cancel")]
+pub struct Cancel {
+    scheduler: SharedScheduler,
+    #[param(typ = "number")]
+    #[output]
+    id: Option<i128>,
+    #[output]
+    cancelled: Option<bool>
+}
+impl Cancel {
+    fn cancel(&mut self) {
+        if let Some(id) = self.id {
+            self.cancelled = Some(u64::try_from(id).map(|id| self.scheduler.borrow_mut().cancel(id)).unwrap_or(false));
         }
     }
-    fn accept(&mut self, idx: u8, param: VariableType) -> bool {
-        match idx {
-            0 => {
-                self.num1 = type_value_to_number(&param);
-                true
-            },
-            1 => {
-                self.num2 = type_value_to_number(&param);
-                true
-            },
-            _ => false
+}
+
+#[derive(Event, Default)]
+#[event(name = "Tick", description = "Does nothing by itself, schedule it to get a timer", call = "tick")]
+pub struct Tick {
+    scheduler: SharedScheduler,
+    #[output]
+    time: Option<u64>
+}
+impl Tick {
+    fn tick(&mut self) {
+        self.time = Some(self.scheduler.borrow().now());
+    }
+}
+
+#[derive(Event, Default)]
+#[event(name = "Now", description = "Reads the VM clock (milliseconds since the start)", call = "now")]
+pub struct Now {
+    scheduler: SharedScheduler,
+    #[output]
+    time: Option<u64>
+}
+impl Now {
+    fn now(&mut self) {
+        self.time = Some(self.scheduler.borrow().now());
+    }
+}
+
+#[derive(Event, Default)]
+#[event(name = "Exit", description = "Stops the VM, nothing runs after the current token except the OnExit listeners (the code goes from 0 to 255, 2, 3 and 4 are reserved for errors)", call = "exit")]
+pub struct Exit {
+    scheduler: SharedScheduler,
+    #[param(default = "0")]
+    #[output]
+    code: Option<i32>
+}
+impl Exit {
+    //The VM and the checker check the code before, this only catches Exit events fired without them
+    fn exit(&mut self) {
+        let code = self.code.unwrap_or(0);
+        if let Err(msg) = check_exit_code(code) {
+            runtime_error_unknown_pos(msg);
         }
+        self.scheduler.borrow_mut().request_exit(code);
     }
-    fn call(&mut self) {
-        if let Some(num1) = &self.num1 {
-            if let Some(num2) = &self.num2 {
-                self.result = Some(num1 % num2);
-            }
+}
+
+//The process can only report 0 to 255, the error exit codes are reserved so they always mean the script failed
+pub fn check_exit_code(code: i32) -> Result<(), String> {
+    if !(0..=255).contains(&code) {
+        Err(format!("Exit code {} is out of range, expected 0 to 255", code))
+    } else if [PARSE_ERROR_EXIT, TYPE_ERROR_EXIT, RUNTIME_ERROR_EXIT].contains(&code) {
+        Err(format!("Exit code {} is reserved for syntax, type and runtime errors", code))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::standard_events::Event;
+    use crate::util::variables::{type_value_to_string, VariableType};
+
+    #[derive(Event, Default)]
+    #[event(name = "Greet", description = "Greets someone", call = "greet")]
+    struct Greet {
+        #[param]
+        #[output]
+        name: Option<String>,
+        #[param(name = "times", typ = "number", default = "1")]
+        count: Option<i128>,
+        #[param(typ = "any")]
+        suffix: Option<String>,
+        #[output(name = "text")]
+        greeting: Option<String>,
+        calls: u8
+    }
+    impl Greet {
+        fn greet(&mut self) {
+            self.calls += 1;
+            let name = self.name.clone().unwrap_or_default();
+            self.greeting = Some(format!("Hello {}{}", name, self.suffix.clone().unwrap_or_default()).repeat(self.count.unwrap_or(1) as usize));
         }
     }
-    fn clone_self(&self) -> Box<dyn Event> {
-        Box::new(MathAdd {
-            num1: self.num1,
-            num2: self.num2,
-            result: self.result
-        })
+
+    fn value(event: &dyn Event, name: &str) -> Option<String> {
+        event.get_var(name).and_then(|value| type_value_to_string(&value))
+    }
+
+    #[test]
+    fn schema() {
+        let schema = Greet::default().schema();
+        assert_eq!(schema.name, "Greet");
+        assert_eq!(schema.description, "Greets someone");
+        let params = schema.params.iter().map(|field| (field.name.as_str(), field.typ.as_str(), field.default.as_deref())).collect::<Vec<_>>();
+        assert_eq!(params, vec![("name", "string", None), ("times", "number", Some("1")), ("suffix", "any", None)]);
+        let outputs = schema.outputs.iter().map(|field| (field.name.as_str(), field.typ.as_str())).collect::<Vec<_>>();
+        assert_eq!(outputs, vec![("name", "?string"), ("text", "?string")]);
+        assert!(Greet::default().check_param_count(2));
+        assert!(!Greet::default().check_param_count(1));
+        assert!(!Greet::default().check_param_count(4));
+    }
+
+    #[test]
+    fn accept_and_call() {
+        let mut event = Greet::default();
+        assert!(event.accept(0, VariableType::String(Some(String::from("Bob")))));
+        assert!(!event.accept(1, VariableType::String(Some(String::from("2")))));
+        assert!(event.accept(1, VariableType::I32(Some(2))));
+        assert!(event.accept(2, VariableType::Char(Some('!'))));
+        assert!(!event.accept(3, VariableType::I32(Some(0))));
+        event.call();
+        assert_eq!(event.calls, 1);
+        assert_eq!(value(&event, "text"), Some(String::from("Hello Bob!Hello Bob!")));
+        assert_eq!(value(&event, "name"), Some(String::from("Bob")));
+        assert!(event.get_var("times").is_none());
+    }
+
+    #[test]
+    fn set_var() {
+        let mut event = Greet::default();
+        assert!(event.set_var("text", VariableType::String(Some(String::from("Hi")))));
+        assert!(!event.set_var("text", VariableType::I32(Some(1))));
+        assert!(!event.set_var("calls", VariableType::U8(Some(1))));
+        assert_eq!(value(&event, "text"), Some(String::from("Hi")));
+        let clone = event.clone_self();
+        assert_eq!(clone.name(), "Greet");
+        assert_eq!(value(clone.as_ref(), "text"), Some(String::from("Hi")));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,16 @@
+extern crate self as event_driven;
+
+pub mod parser;
+pub mod checker;
+pub mod util;
+pub mod event;
+pub mod vm;
+pub mod builder;
+
+pub use event_derive::Event;
+pub use crate::builder::{BuildError, VMBuilder};
+pub use crate::event::registry::EventRegistry;
+pub use crate::event::standard_events::Event;
+pub use crate::util::RuntimeError;
+pub use crate::util::variables::VariableType;
+pub use crate::vm::VM;
@@ -1,18 +1,11 @@
-#![feature(once_cell)]
-#![feature(fn_traits)]
-#![feature(panic_info_message)]
-
-pub mod parser;
-pub mod util;
-pub mod event;
-pub mod vm;
-
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use backtrace::Backtrace;
 use rustop::opts;
-use crate::event::event_pipeline::EventPipeline;
-use crate::vm::VM;
+use event_driven::{BuildError, EventRegistry, RuntimeError, VM};
+use event_driven::event::clock::RealClock;
+use event_driven::event::scheduler::Scheduler;
+use event_driven::util::VALUE_ERROR_CODE;
+use event_driven::vm::{PARSE_ERROR_EXIT, RUNTIME_ERROR_EXIT, TYPE_ERROR_EXIT};
+
 
 fn main() {
     std::panic::set_hook(Box::new(|panic_info| {
@@ -24,38 +17,84 @@ fn main() {
         } else {
             eprintln!("Backtrace removed. Run in debug mode to show")
         }
-        eprintln!("{:?}", panic_info.message().ok_or_else(|| {}).map_err(|_| "No message provided").unwrap());
+        eprintln!("{}", panic_info.payload_as_str().unwrap_or("No message provided"));
         eprintln!("----- Error -----");
     }));
 
     let (args,_) = opts! {
-        param action:String, name:"action", desc:"Action to execute. Actions: simulate, compile";
-        param file:String, name:"file", desc:"File to perform the action on";
+        param action:String, name:"action", desc:"Action to execute. Actions: simulate, compile, docs";
+        param file:Option<String>, name:"file", desc:"File to perform the action on";
+        opt realtime:bool, desc:"Simulate with the real clock instead of jumping between scheduled events";
+        opt until:Option<u64>, desc:"Stop the simulation once the clock reaches this time (milliseconds)";
+        opt warn_shadowing:bool, desc:"Warn when a variable shadows one of an outer scope";
     }.parse_or_exit();
 
     match args.action.as_str() {
         "simulate" => {
-            let lines = read(args.file);
-            let split = parser::pre_parse(lines);
-            let tokens = parser::tokenize(split);
-            let listeners = parser::split(tokens);
-
-            let mut vm = VM {
-                pipeline: EventPipeline {
-                    listeners
+            let mut builder = VM::builder()
+                .file(args.file.expect("No file specified!").as_str())
+                .warn_shadowing(args.warn_shadowing);
+            if args.realtime {
+                builder = builder.clock(RealClock::new());
+            }
+            match builder.build() {
+                Ok(vm) => {
+                    let result = if let Some(until) = args.until {
+                        vm.start().and_then(|_| vm.advance(until)).and_then(|_| {
+                            let code = vm.exit_code().unwrap_or(0);
+                            vm.exit(code).map(|_| code)
+                        })
+                    } else {
+                        vm.run()
+                    };
+                    match result {
+                        Ok(code) => std::process::exit(code),
+                        Err(error) => {
+                            print_runtime_error(&error);
+                            if let Err(error) = vm.exit(RUNTIME_ERROR_EXIT) {
+                                print_runtime_error(&error);
+                            }
+                            std::process::exit(RUNTIME_ERROR_EXIT)
+                        }
+                    }
+                }
+                Err(BuildError::File(msg)) => {
+                    eprintln!("ERROR: {}", msg);
+                    std::process::exit(PARSE_ERROR_EXIT)
                 }
-            };
-            vm.start();
+                Err(BuildError::Syntax(msg, pos)) => {
+                    match pos {
+                        Some(pos) => eprintln!("ERROR: {} (at {})", msg, pos),
+                        None => eprintln!("ERROR: {} (unknown position)", msg)
+                    }
+                    std::process::exit(PARSE_ERROR_EXIT)
+                }
+                Err(error) => {
+                    let errors = error.errors();
+                    errors.iter().for_each(|(msg, pos)| eprintln!("ERROR: {} (at {})", msg, pos));
+                    eprintln!("ERROR: Found {} error(s)", errors.len());
+                    std::process::exit(TYPE_ERROR_EXIT)
+                }
+            }
         }
         "compile" => {
         }
+        "docs" => {
+            let registry = EventRegistry::new(&Scheduler::shared());
+            registry.names().iter().filter_map(|name| registry.get(name)).for_each(|event| {
+                println!("{}\n", event.schema().documentation());
+            });
+        }
         _ => {
             eprintln!("Invalid action! Try --help for help");
         }
     }
 }
 
-fn read(path: String) -> Vec<String> {
-    let file = File::open(path).expect("Failed to open file!");
-    BufReader::new(file).lines().map(|line| line.expect("Failed to read line for some reason!")).collect()
-}
\ No newline at end of file
+fn print_runtime_error(error: &RuntimeError) {
+    if error.code == VALUE_ERROR_CODE {
+        eprintln!("ERROR: {}", error);
+    } else {
+        eprintln!("RUNTIME ERROR: {}", error);
+    }
+}
@@ -1,10 +1,14 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use crate::event::event_pipeline::EventListener;
+use substring::Substring;
+use crate::event::event_pipeline::{EventListener, priority_from_name};
+use crate::event::schema::{EventField, EventSchema};
 
 use crate::util::{error, error_str, error_unknown_pos, warning};
 use crate::util::debug::debug;
 use crate::util::position::Position;
-use crate::util::token::{Token, TokenType};
+use crate::util::token::{EventParam, Function, Keyword, MatchArm, Token, TokenType};
+use crate::util::variables::{CastMode, check_type_name, EnumType, EnumVariant, function_call, is_literal_of_type};
 
 pub fn pre_parse(lines: Vec<String>) -> Vec<(Position, String)> {
     let lines = lines.iter().fold((0 as u16, vec![]), |acc, line| {
@@ -15,7 +19,7 @@ pub fn pre_parse(lines: Vec<String>) -> Vec<(Position, String)> {
 
         (line_number, list)
     }).1;
-    let (unclosed, _, pos, list) = lines.iter().map(|line| {
+    let (unclosed_string, unclosed_call, _, pos, list) = lines.iter().flat_map(|line| {
         let (line_number, line) = line;
 
         line.split_whitespace().fold(vec![], |mut list: Vec<(Position, String)>, instr| {
@@ -29,8 +33,8 @@ pub fn pre_parse(lines: Vec<String>) -> Vec<(Position, String)> {
 
             list
         })
-    }).flatten().fold((false, String::new(), Position::default(), vec![]), |acc, instr| {
-            let (mut in_string, mut s, mut pos, mut list) = acc;
+    }).fold((false, false, String::new(), Position::default(), vec![]), |acc, instr| {
+            let (mut in_string, mut in_call, mut s, mut pos, mut list) = acc;
             let (instr_pos, mut instr) = instr;
 
             let end_semicolon = instr.ends_with(';');
@@ -38,8 +42,20 @@ pub fn pre_parse(lines: Vec<String>) -> Vec<(Position, String)> {
                 instr.remove(instr.len() - 1);
             }
 
-            if in_string {
-                if instr.ends_with(r#"""#) {
+            if in_call {
+                s.push(' ');
+                s.push_str(instr.as_str());
+                if end_semicolon {
+                    s.push(';');
+                }
+                if open_parens(&s) <= 0 {
+                    list.push((pos, s));
+                    s = String::new();
+                    pos = Position::default();
+                    in_call = false;
+                }
+            } else if in_string {
+                if instr.trim_end_matches(',').ends_with('"') {
                     s.push_str(instr.as_str());
                     if end_semicolon {
                         s.push(';');
@@ -48,17 +64,26 @@ pub fn pre_parse(lines: Vec<String>) -> Vec<(Position, String)> {
                     s = String::new();
                     pos = Position::default();
                     in_string = false;
+                } else {
+                    s.push_str(instr.as_str());
+                    if end_semicolon {
+                        s.push(';');
+                    }
+                    s.push(' ');
                 }
-                s.push_str(instr.as_str());
-                if end_semicolon {
-                    s.push(';');
-                }
-                s.push(' ');
             } else {
-                if instr.starts_with(r#"""#) {
+                //Function calls and headers like add(1, 2) become one instruction
+                if !instr.starts_with('"') && !instr.starts_with('(') && open_parens(&instr) > 0 {
+                    in_call = true;
+                    pos = instr_pos;
+                    s = instr;
+                    if end_semicolon {
+                        s.push(';');
+                    }
+                } else if instr.starts_with(r#"""#) {
                     instr.remove(0);
                     pos = instr_pos;
-                    if instr.ends_with(r#"""#) {
+                    if instr.trim_end_matches(',').ends_with('"') {
                         instr.insert(0, '"');
                         if end_semicolon {
                             instr.push(';');
@@ -80,20 +105,25 @@ pub fn pre_parse(lines: Vec<String>) -> Vec<(Position, String)> {
                 }
             }
 
-            (in_string, s, pos, list)
+            (in_string, in_call, s, pos, list)
         });
 
-    if unclosed {
+    if unclosed_string {
         error_str("String not closed", pos);
+    } else if unclosed_call {
+        error_str("Parenthesis not closed", pos);
     }
 
     list
 }
 
+//scope depth, static set, dynamic set, cast, enum variant, listener type, event params, tokens
+type TokenizeState = (i32, bool, bool, Option<CastMode>, bool, Option<String>, Vec<EventParam>, Vec<Token>);
+
 pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
-    let (scope_depth, _, _, _, _, _, list)
-            = instructions.iter().fold((0, false, false, false, None, vec![], vec![]), |acc: (i32, bool, bool, bool, Option<String>, Vec<String>, Vec<Token>), instr| {
-        let (mut scope_depth, mut var_static_set, mut var_dynamic_set, mut var_cast, mut listener_type, mut event_params, mut list) = acc;
+    let (scope_depth, _, _, _, _, _, _, list)
+            = instructions.iter().fold((0, false, false, None, false, None, vec![], vec![]), |acc: TokenizeState, instr| {
+        let (mut scope_depth, mut var_static_set, mut var_dynamic_set, mut var_cast, mut enum_variant, mut listener_type, mut event_params, mut list) = acc;
         let (pos,mut instr) = instr.clone();
 
         let mut end_command_semicolon = instr.ends_with(';');
@@ -102,14 +132,127 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
         }
 
         let mut parsed = false;
-        if let Some(_) = listener_type.clone() {
-            if instr.starts_with('#') {
+        if listener_type.is_some() && scope_depth == 0 && instr.starts_with('[') {
+            let priority = instr.strip_prefix("[priority=").and_then(|priority| priority.strip_suffix(']')).unwrap_or_else(|| {
+                error(format!("Invalid listener annotation {}, expected [priority=<priority>]", instr), pos);
+                unreachable!()
+            });
+            if let Some(priority) = priority_from_name(priority) {
+                list.push(Token {
+                    token: TokenType::ListenerPriority(priority),
+                    pos
+                });
+            } else {
+                error(format!("Unknown listener priority {} (expected lowest, low, normal, high, highest, monitor or a number)", priority), pos);
+            }
+            parsed = true;
+        } else if let Some(typ) = listener_type.clone() {
+            if typ == "@enum" && scope_depth == 2 && instr != "{" && instr != "}" {
+                if enum_variant {
+                    if let Some(Token { token: TokenType::EnumVariant(variant, fields), .. }) = list.last_mut() {
+                        if instr.starts_with('(') && instr.ends_with(')') {
+                            if let Some(field) = fields.last_mut().filter(|field| field.1.is_empty()) {
+                                field.1 = instr.substring(1, instr.len() - 1).to_string();
+                            } else {
+                                error(format!("Missing field name before type {} in enum variant {}", instr, variant), pos);
+                            }
+                        } else if let Some(field) = fields.last().filter(|field| field.1.is_empty()) {
+                            error(format!("Missing type for field {} in enum variant {}", field.0, variant), pos);
+                        } else {
+                            fields.push((instr.clone(), String::new()));
+                        }
+                    }
+                } else {
+                    list.push(Token {
+                        token: TokenType::EnumVariant(instr.clone(), vec![]),
+                        pos
+                    });
+                    enum_variant = true;
+                }
+                if end_command_semicolon {
+                    if let Some(Token { token: TokenType::EnumVariant(variant, fields), .. }) = list.last() {
+                        if let Some(field) = fields.last().filter(|field| field.1.is_empty()) {
+                            error(format!("Missing type for field {} in enum variant {}", field.0, variant), pos);
+                        }
+                        debug(format!("enum variant {} with fields {:?}", variant, fields));
+                    }
+                    enum_variant = false;
+                    end_command_semicolon = false;
+                }
+                parsed = true;
+            } else if matches!(list.last().map(|tok| &tok.token), Some(TokenType::OutputBinding(_, false))) {
+                if let Some(Token { token: TokenType::OutputBinding(bindings, closed), .. }) = list.last_mut() {
+                    *closed = push_binding(bindings, &instr, pos);
+                }
+                parsed = true;
+            } else if instr.starts_with('(') && !matches!(list.last().map(|tok| &tok.token), Some(TokenType::Raw(_))) {
+                let mut bindings = vec![];
+                let closed = push_binding(&mut bindings, instr.substring(1, instr.len()), pos);
+                list.push(Token {
+                    token: TokenType::OutputBinding(bindings, closed),
+                    pos
+                });
+                parsed = true;
+            } else if instr == "match" {
+                list.push(Token {
+                    token: TokenType::Keyword(Keyword::Match),
+                    pos
+                });
+                parsed = true;
+            } else if instr == "try" {
+                list.push(Token {
+                    token: TokenType::Keyword(Keyword::Try),
+                    pos
+                });
+                parsed = true;
+            } else if instr == "catch" {
+                if !matches!(list.last().map(|tok| &tok.token), Some(TokenType::Try(_, None, _))) {
+                    error_str("catch without a try block before it", pos);
+                }
+                list.push(Token {
+                    token: TokenType::Keyword(Keyword::Catch),
+                    pos
+                });
+                parsed = true;
+            } else if instr == "return" {
+                if end_command_semicolon {
+                    list.push(Token {
+                        token: TokenType::Return(None),
+                        pos
+                    });
+                    end_command_semicolon = false;
+                } else {
+                    list.push(Token {
+                        token: TokenType::Keyword(Keyword::Return),
+                        pos
+                    });
+                }
+                parsed = true;
+            } else if let Some(TokenType::Keyword(Keyword::Return)) = list.last().map(|tok| &tok.token) {
+                list.pop();
+                list.push(Token {
+                    token: TokenType::Return(Some(instr.clone())),
+                    pos
+                });
+                if end_command_semicolon {
+                    end_command_semicolon = false;
+                } else {
+                    error_str("Missing a semicolon after return", pos);
+                }
+                parsed = true;
+            } else if instr.starts_with('#') {
                 instr.remove(0);
+                let queued = instr.starts_with('>');
+                if queued {
+                    instr.remove(0);
+                }
                 if instr.is_empty() {
                     error_str("No event to call specified", pos);
                 }
 
-                if var_dynamic_set {
+                if var_dynamic_set && queued {
+                    error_str("Can't pipe a queued event to a variable, it runs after the current listener", pos);
+                } else if var_dynamic_set {
                     if let Some(tok) = list.pop() {
                         if let TokenType::InitVariable(name, typ, _, _, _) = tok.token {
                             if typ.as_str() != "event" {
@@ -126,12 +269,23 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                                 pos
                             });
                             var_dynamic_set = false;
+                        } else if let TokenType::OutputBinding(bindings, true) = tok.token {
+                            list.push(Token {
+                                token: TokenType::InitVariableOutputs(bindings, instr.clone(), None),
+                                pos
+                            });
+                            var_dynamic_set = false;
                         } else {
                             error_str("Illegal event call (syntax doesn't make sense)", pos);
                         }
                     } else {
                         error_str("Can't pipe event to empty variable", pos);
                     }
+                } else if queued {
+                    list.push(Token {
+                        token: TokenType::QueueEvent(instr.clone(), None),
+                        pos
+                    });
                 } else {
                     list.push(Token {
                         token: TokenType::CallEvent(instr.clone(), None),
@@ -151,9 +305,12 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                     if let TokenType::Raw(name) = tok.token {
                         debug(format!("variable {} of type {}", name, instr.clone()));
                         list.push(Token {
-                            token: TokenType::InitVariable(name, instr.clone(), true, false, None),
+                            token: TokenType::InitVariable(name, instr.clone(), true, None, None),
                             pos
                         });
+                        if typ == "@custom" && end_command_semicolon {
+                            end_command_semicolon = false;
+                        }
                         parsed = true;
                     } else {
                         error_str("No variable name specified", pos);
@@ -173,23 +330,78 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                 }
                 var_static_set = true;
                 parsed = true;
-            } else if instr == "<-" || instr == "<=" {
+            } else if instr == "<-" || CastMode::from_operator(&instr).is_some() {
                 if let Some(tok) = list.last() {
                     if let TokenType::Raw(_) = &tok.token {
                     } else if let TokenType::InitVariable(_, _, _, _, _) = &tok.token {
                     } else if let TokenType::CallEvent(_, _) = &tok.token {
+                    } else if let TokenType::QueueEvent(_, _) = &tok.token {
                     } else if let TokenType::InitVariableEvent(_, _, _) = &tok.token {
                     } else if let TokenType::VariableEventSet(_, _, _) = &tok.token {
+                    } else if let TokenType::InitVariableEnum(_, _, _, _) = &tok.token {
+                    } else if let TokenType::VariableEnumSet(_, _, _) = &tok.token {
+                    } else if let TokenType::InitVariableOutputs(_, _, _) = &tok.token {
+                    } else if let TokenType::OutputBinding(_, true) = &tok.token {
+                        if instr != "<-" {
+                            error_str("Output bindings can't be cast", pos);
+                        }
                     } else {
                         error_str("No dynamic target specified", pos);
                     }
                 } else {
                     error_str("No dynamic target specified", pos);
                 }
-                var_cast = instr == "<=";
+                var_cast = CastMode::from_operator(&instr);
                 var_dynamic_set = true;
                 parsed = true;
             } else if instr == "{" {
+                if let [.., Token { token: TokenType::Keyword(Keyword::Match), .. }, Token { token: TokenType::Raw(name), .. }] = list.as_slice() {
+                    let name = name.clone();
+                    list.truncate(list.len() - 2);
+                    debug(format!("match on {}", name));
+                    list.push(Token {
+                        token: TokenType::Match(name, vec![]),
+                        pos
+                    });
+                } else if let Some(TokenType::Keyword(Keyword::Match)) = list.last().map(|tok| &tok.token) {
+                    error_str("No variable to match on specified", pos);
+                } else if let Some(TokenType::Keyword(Keyword::Try)) = list.last().map(|tok| &tok.token) {
+                    list.pop();
+                    list.push(Token {
+                        token: TokenType::Try(vec![], None, vec![]),
+                        pos
+                    });
+                } else if let [.., Token { token: TokenType::Try(_, catch, _), .. }, Token { token: TokenType::Keyword(Keyword::Catch), .. }, Token { token: TokenType::Raw(name), .. }] = list.as_mut_slice() {
+                    *catch = Some(name.clone());
+                    list.truncate(list.len() - 2);
+                } else if let Some(TokenType::Keyword(Keyword::Catch)) = list.last().map(|tok| &tok.token) {
+                    error_str("Missing error variable name after catch", pos);
+                } else if let Some(Token { token: TokenType::Match(_, _), .. }) = open_scope(&list).and_then(|idx| idx.checked_sub(1)).map(|idx| &list[idx]) {
+                    let header_start = list.iter().rposition(|tok| !matches!(tok.token, TokenType::Raw(_))).unwrap() + 1;
+                    let header = list.split_off(header_start).into_iter().map(|tok| {
+                        if let TokenType::Raw(s) = tok.token { s } else { unreachable!() }
+                    }).collect::<Vec<String>>();
+                    if let Some((variant, bindings)) = header.split_first() {
+                        if variant != "_" && !variant.contains("::") {
+                            error(format!("Invalid match pattern {}, expected Enum::Variant or _", variant), pos);
+                        }
+                        if variant == "_" && !bindings.is_empty() {
+                            error_str("Wildcard match arm can't bind fields", pos);
+                        }
+                        debug(format!("match arm {} binding {:?}", variant, bindings));
+                        list.push(Token {
+                            token: TokenType::MatchArm(MatchArm {
+                                variant: variant.clone(),
+                                bindings: bindings.to_vec(),
+                                tokens: vec![],
+                                pos
+                            }),
+                            pos
+                        });
+                    } else {
+                        error_str("Missing pattern for match arm", pos);
+                    }
+                }
                 list.push(Token {
                     token: TokenType::ScopeStart,
                     pos
@@ -198,15 +410,71 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                 debug(format!("entered scope (now level {})", scope_depth));
                 parsed = true;
             } else if instr == "}" {
-                list.push(Token {
-                    token: TokenType::ScopeEnd,
-                    pos
-                });
+                if enum_variant {
+                    error_str("Missing a semicolon after enum variant", pos);
+                }
+                let open = open_scope(&list);
+                match open.and_then(|idx| idx.checked_sub(1)).map(|idx| &list[idx].token) {
+                    Some(TokenType::MatchArm(_)) => {
+                        let body = list.split_off(open.unwrap() + 1);
+                        list.pop();
+                        if let Some(Token { token: TokenType::MatchArm(arm), .. }) = list.last_mut() {
+                            arm.tokens = body;
+                        }
+                    }
+                    Some(TokenType::Try(_, _, _)) => {
+                        let body = list.split_off(open.unwrap() + 1);
+                        list.pop();
+                        if let Some(Token { token: TokenType::Try(tokens, catch, catch_tokens), .. }) = list.last_mut() {
+                            if catch.is_some() {
+                                *catch_tokens = body;
+                            } else {
+                                *tokens = body;
+                            }
+                        }
+                    }
+                    Some(TokenType::Match(_, _)) => {
+                        let arms = list.split_off(open.unwrap() + 1).into_iter().map(|tok| {
+                            if let TokenType::MatchArm(arm) = tok.token {
+                                arm
+                            } else {
+                                error_str("Only match arms are allowed directly inside a match block", tok.pos);
+                                unreachable!()
+                            }
+                        }).collect::<Vec<MatchArm>>();
+                        list.pop();
+                        if arms.is_empty() {
+                            error_str("Match block without arms", pos);
+                        }
+                        if let Some(Token { token: TokenType::Match(_, match_arms), .. }) = list.last_mut() {
+                            *match_arms = arms;
+                        }
+                    }
+                    _ => {
+                        list.push(Token {
+                            token: TokenType::ScopeEnd,
+                            pos
+                        });
+                    }
+                }
                 scope_depth -= 1;
                 if scope_depth < 0 {
                     error(format!("Tried to exit non-existent scope (scope depth: {})", scope_depth), pos);
                 }
                 debug(format!("exited scope (now level {})", scope_depth));
+                if scope_depth == 0 && typ == "fn" {
+                    if let Some(idx) = list.iter().rposition(|tok| matches!(tok.token, TokenType::Function(_))) {
+                        let body = list.split_off(idx + 1);
+                        if let Some(Token { token: TokenType::Function(function), .. }) = list.last_mut() {
+                            debug(format!("exited function {}", function.name));
+                            function.tokens = body;
+                        }
+                    }
+                    listener_type = None;
+                } else if scope_depth == 0 {
+                    debug(format!("exited listener {}", typ));
+                    listener_type = None;
+                }
                 parsed = true;
             } else {
                 if var_static_set {
@@ -223,7 +491,7 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                             }
                             debug(format!("initiate variable {} of type {} to {}", name, typ, instr.clone()));
                             list.push(Token {
-                                token: TokenType::InitVariable(name.clone(), typ.clone(), true, false, Some(instr.clone())),
+                                token: TokenType::InitVariable(name.clone(), typ.clone(), true, None, Some(instr.clone())),
                                 pos
                             });
                         } else {
@@ -239,8 +507,28 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                     parsed = true;
                 } else if var_dynamic_set {
                     let mut require_semicolon = true;
+                    let param = instr.strip_suffix(',').unwrap_or(instr.as_str()).to_string();
                     if let Some(tok) = list.pop() {
-                        if let TokenType::Raw(name) = &tok.token {
+                        if instr.contains("::") && function_call(&param).is_none() && matches!(tok.token, TokenType::Raw(_) | TokenType::InitVariable(_, _, _, _, None)) {
+                            let token = if let TokenType::InitVariable(name, typ, _, _, _) = &tok.token {
+                                debug(format!("initiate variable {} of type {} to {}", name, typ, instr.clone()));
+                                TokenType::InitVariableEnum(name.clone(), typ.clone(), instr.clone(), None)
+                            } else if let TokenType::Raw(name) = &tok.token {
+                                debug(format!("change variable {} to {}", name, instr.clone()));
+                                TokenType::VariableEnumSet(name.clone(), instr.clone(), None)
+                            } else {
+                                unreachable!()
+                            };
+                            list.push(Token {
+                                token,
+                                pos
+                            });
+                            if end_command_semicolon {
+                                var_dynamic_set = false;
+                            } else {
+                                require_semicolon = false;
+                            }
+                        } else if let TokenType::Raw(name) = &tok.token {
                             debug(format!("change variable {} to {}", name, instr.clone()));
                             list.push(Token {
                                 token: TokenType::VariableDynamicSet(name.clone(), instr.clone(), var_cast),
@@ -258,7 +546,7 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                             });
                             var_dynamic_set = false;
                         } else if let TokenType::CallEvent(name, _) = &tok.token {
-                            event_params.push(instr.clone());
+                            push_param(&mut event_params, &param, end_command_semicolon, pos);
                             if end_command_semicolon {
                                 list.push(Token {
                                     token: TokenType::CallEvent(name.clone(), Some(event_params)),
@@ -270,8 +558,21 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                                 list.push(tok);
                                 require_semicolon = false;
                             }
+                        } else if let TokenType::QueueEvent(name, _) = &tok.token {
+                            push_param(&mut event_params, &param, end_command_semicolon, pos);
+                            if end_command_semicolon {
+                                list.push(Token {
+                                    token: TokenType::QueueEvent(name.clone(), Some(event_params)),
+                                    pos
+                                });
+                                event_params = vec![];
+                                var_dynamic_set = false;
+                            } else {
+                                list.push(tok);
+                                require_semicolon = false;
+                            }
                         } else if let TokenType::InitVariableEvent(event, name, _) = &tok.token {
-                            event_params.push(instr.clone());
+                            push_param(&mut event_params, &param, end_command_semicolon, pos);
                             if end_command_semicolon {
                                 list.push(Token {
                                     token: TokenType::InitVariableEvent(event.clone(), name.clone(), Some(event_params)),
@@ -284,7 +585,7 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                                 require_semicolon = false;
                             }
                         } else if let TokenType::VariableEventSet(name, event, _) = &tok.token {
-                            event_params.push(instr.clone());
+                            push_param(&mut event_params, &param, end_command_semicolon, pos);
                             if end_command_semicolon {
                                 list.push(Token {
                                     token: TokenType::VariableEventSet(name.clone(), event.clone(), Some(event_params)),
@@ -296,6 +597,47 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                                 list.push(tok);
                                 require_semicolon = false;
                             }
+                        } else if let TokenType::InitVariableOutputs(bindings, event, _) = &tok.token {
+                            push_param(&mut event_params, &param, end_command_semicolon, pos);
+                            if end_command_semicolon {
+                                list.push(Token {
+                                    token: TokenType::InitVariableOutputs(bindings.clone(), event.clone(), Some(event_params)),
+                                    pos
+                                });
+                                event_params = vec![];
+                                var_dynamic_set = false;
+                            } else {
+                                list.push(tok);
+                                require_semicolon = false;
+                            }
+                        } else if let TokenType::OutputBinding(_, _) = &tok.token {
+                            error_str("Output bindings need an event call, like (q, r) <- #Event", pos);
+                        } else if let TokenType::InitVariableEnum(name, typ, variant, _) = &tok.token {
+                            push_param(&mut event_params, &param, end_command_semicolon, pos);
+                            if end_command_semicolon {
+                                list.push(Token {
+                                    token: TokenType::InitVariableEnum(name.clone(), typ.clone(), variant.clone(), Some(event_params)),
+                                    pos
+                                });
+                                event_params = vec![];
+                                var_dynamic_set = false;
+                            } else {
+                                list.push(tok);
+                                require_semicolon = false;
+                            }
+                        } else if let TokenType::VariableEnumSet(name, variant, _) = &tok.token {
+                            push_param(&mut event_params, &param, end_command_semicolon, pos);
+                            if end_command_semicolon {
+                                list.push(Token {
+                                    token: TokenType::VariableEnumSet(name.clone(), variant.clone(), Some(event_params)),
+                                    pos
+                                });
+                                event_params = vec![];
+                                var_dynamic_set = false;
+                            } else {
+                                list.push(tok);
+                                require_semicolon = false;
+                            }
                         } else {
                             error_str("No variable name specified", pos);
                         }
@@ -307,6 +649,14 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                     }
                     parsed = true;
                 }
+                if let (false, true, Some((name, args))) = (parsed, end_command_semicolon, function_call(&instr)) {
+                    list.push(Token {
+                        token: TokenType::CallFunction(name, args),
+                        pos
+                    });
+                    end_command_semicolon = false;
+                    parsed = true;
+                }
                 if !parsed {
                     list.push(Token {
                         token: TokenType::Raw(instr.clone()),
@@ -315,6 +665,44 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
                     parsed = true;
                 }
             }
+        } else if instr == "fn" {
+            list.push(Token {
+                token: TokenType::Keyword(Keyword::Fn),
+                pos
+            });
+            parsed = true;
+        } else if let Some(TokenType::Keyword(Keyword::Fn)) = list.last().map(|tok| &tok.token) {
+            list.pop();
+            list.push(Token {
+                token: TokenType::Function(function_header(&instr, pos)),
+                pos
+            });
+            parsed = true;
+        } else if matches!(list.last().map(|tok| &tok.token), Some(TokenType::Function(function)) if function.tokens.is_empty()) {
+            if let Some(Token { token: TokenType::Function(function), .. }) = list.last_mut() {
+                if instr == "{" {
+                    if function.returns.as_deref() == Some("") {
+                        error(format!("Missing return type after -> for function {}", function.name), pos);
+                    }
+                    debug(format!("entered function {}", function.name));
+                } else if instr == "->" && function.returns.is_none() {
+                    function.returns = Some(String::new());
+                } else if function.returns.as_deref() == Some("") {
+                    //Types can be enums declared anywhere in the file, the checker resolves them
+                    function.returns = Some(instr.clone());
+                } else {
+                    error(format!("Unexpected Token: {} (expected -> or {{ after the function header)", instr), pos);
+                }
+            }
+            if instr == "{" {
+                list.push(Token {
+                    token: TokenType::ScopeStart,
+                    pos
+                });
+                scope_depth += 1;
+                listener_type = Some(String::from("fn"));
+            }
+            parsed = true;
         } else {
             list.push(Token {
                 token: TokenType::Listener(instr.clone()),
@@ -336,7 +724,7 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
             error(format!("Unexpected Token: {}", instr), pos);
         }
 
-        (scope_depth, var_static_set, var_dynamic_set, var_cast, listener_type, event_params, list)
+        (scope_depth, var_static_set, var_dynamic_set, var_cast, enum_variant, listener_type, event_params, list)
     });
 
     if scope_depth > 0 {
@@ -348,10 +736,12 @@ pub fn tokenize(instructions: Vec<(Position, String)>) -> Vec<Token> {
 
 pub fn split(tokens: Vec<Token>) -> HashMap<String, Vec<EventListener>> {
     let mut map: HashMap<String, Vec<EventListener>> = HashMap::new();
-    tokens.iter().fold((0 as u8, None, vec![]), |acc: (u8, Option<String>, Vec<Token>), token| {
-        let (mut scope_depth, mut event_type, mut list) = acc;
+    tokens.iter().fold((0u8, None, 0, vec![]), |acc: (u8, Option<String>, i32, Vec<Token>), token| {
+        let (mut scope_depth, mut event_type, mut priority, mut list) = acc;
 
-        if let Some(typ) = &event_type {
+        if let (Some(_), TokenType::ListenerPriority(listener_priority)) = (&event_type, &token.token) {
+            priority = *listener_priority;
+        } else if let Some(typ) = &event_type {
             if let TokenType::ScopeStart = &token.token {
                 scope_depth += 1;
             } else if let TokenType::ScopeEnd = &token.token {
@@ -361,23 +751,362 @@ pub fn split(tokens: Vec<Token>) -> HashMap<String, Vec<EventListener>> {
 
             if scope_depth == 0 {
                 if let Some(listeners) = map.get_mut(typ) {
-                    listeners.push(EventListener {
-                        tokens: list
+                    listeners.push(EventListener::Script {
+                        tokens: list,
+                        priority
                     });
                 } else {
-                    map.insert(typ.clone(), vec![EventListener {
-                        tokens: list
+                    map.insert(typ.clone(), vec![EventListener::Script {
+                        tokens: list,
+                        priority
                     }]);
                 }
                 event_type = None;
+                priority = 0;
                 list = vec![];
             }
         } else if let TokenType::Listener(typ) = &token.token {
             event_type = Some(typ.clone());
         }
 
-        (scope_depth, event_type.clone(), list)
+        (scope_depth, event_type.clone(), priority, list)
+    });
+
+    //Stable, so listeners with the same priority stay in file order
+    map.values_mut().for_each(|listeners| listeners.sort_by_key(|listener| Reverse(listener.priority())));
+    map
+}
+
+pub fn enums(tokens: &[Token]) -> HashMap<String, EnumType> {
+    let mut map: HashMap<String, EnumType> = HashMap::new();
+    tokens.iter().fold((0u8, false, None), |acc: (u8, bool, Option<EnumType>), token| {
+        let (mut scope_depth, mut in_block, mut enum_type) = acc;
+
+        match &token.token {
+            TokenType::Listener(typ) => {
+                in_block = typ == "@enum";
+            }
+            _ if !in_block => {}
+            TokenType::ScopeStart => {
+                scope_depth += 1;
+                if scope_depth == 2 && enum_type.is_none() {
+                    error_str("Missing name for enum", token.pos);
+                }
+            }
+            TokenType::ScopeEnd => {
+                scope_depth -= 1;
+                if scope_depth == 1 {
+                    if let Some(typ) = enum_type.take() {
+                        if typ.variants.is_empty() {
+                            error(format!("Enum {} has no variants", typ.name), token.pos);
+                        }
+                        debug(format!("enum {} with variants {:?}", typ.name, typ.variants));
+                        map.insert(typ.name.clone(), typ);
+                    }
+                } else if scope_depth == 0 {
+                    in_block = false;
+                }
+            }
+            TokenType::Raw(name) if scope_depth == 1 && enum_type.is_none() => {
+                if map.contains_key(name) {
+                    error(format!("Enum {} declared twice", name), token.pos);
+                }
+                enum_type = Some(EnumType {
+                    name: name.clone(),
+                    variants: vec![]
+                });
+            }
+            TokenType::EnumVariant(name, fields) if scope_depth == 2 => {
+                if let Some(typ) = &mut enum_type {
+                    if typ.variants.iter().any(|variant| variant.name == *name) {
+                        error(format!("Variant {} declared twice in enum {}", name, typ.name), token.pos);
+                    }
+                    fields.iter().for_each(|field| check_type_name(&field.1, token.pos));
+                    typ.variants.push(EnumVariant {
+                        name: name.clone(),
+                        fields: fields.clone()
+                    });
+                }
+            }
+            _ => {
+                error_str("Unexpected instruction in enum declaration", token.pos);
+            }
+        }
+
+        (scope_depth, in_block, enum_type)
     });
 
     map
-}
\ No newline at end of file
+}
+
+//Every schema comes with the position of its name, for errors that come up when it's registered
+pub fn custom_events(tokens: &[Token]) -> HashMap<String, (EventSchema, Position)> {
+    let mut map: HashMap<String, (EventSchema, Position)> = HashMap::new();
+    tokens.iter().fold((0u8, false, None), |acc: (u8, bool, Option<(EventSchema, Position)>), token| {
+        let (mut scope_depth, mut in_block, mut schema) = acc;
+
+        match &token.token {
+            TokenType::Listener(typ) => {
+                in_block = typ == "@custom";
+            }
+            _ if !in_block => {}
+            TokenType::ScopeStart => {
+                scope_depth += 1;
+                if scope_depth == 2 && schema.is_none() {
+                    error_str("Missing name for custom event", token.pos);
+                }
+            }
+            TokenType::ScopeEnd => {
+                scope_depth -= 1;
+                if scope_depth == 1 {
+                    if let Some((schema, pos)) = schema.take() {
+                        debug(format!("custom event {} with params {:?}", schema.name, schema.params));
+                        map.insert(schema.name.clone(), (schema, pos));
+                    }
+                } else if scope_depth == 0 {
+                    in_block = false;
+                }
+            }
+            TokenType::Raw(name) if scope_depth == 1 && schema.is_none() => {
+                if map.contains_key(name) {
+                    error(format!("Custom event {} declared twice", name), token.pos);
+                }
+                schema = Some((EventSchema::new(name, "Custom event"), token.pos));
+            }
+            TokenType::InitVariable(name, typ, true, None, value) if scope_depth == 2 => {
+                if let Some((schema, _)) = &mut schema {
+                    if schema.params.iter().any(|field| field.name == *name) {
+                        error(format!("Field {} declared twice in custom event {}", name, schema.name), token.pos);
+                    }
+                    check_type_name(typ, token.pos);
+                    let field = if let Some(value) = value {
+                        if !(is_literal_of_type(typ.trim_start_matches('?'), value) || value == "null" && typ.starts_with('?')) {
+                            error(format!("{} is not of type {}", value, typ), token.pos);
+                        }
+                        EventField::with_default(name, typ, value)
+                    } else {
+                        EventField::new(name, typ)
+                    };
+                    schema.params.push(field);
+                    schema.outputs.push(EventField::new(name, typ));
+                }
+            }
+            _ => {
+                error_str("Unexpected instruction in custom event declaration", token.pos);
+            }
+        }
+
+        (scope_depth, in_block, schema)
+    });
+
+    map
+}
+
+pub fn functions(tokens: &[Token]) -> HashMap<String, Function> {
+    tokens.iter().fold(HashMap::new(), |mut map, token| {
+        if let TokenType::Function(function) = &token.token {
+            if map.contains_key(&function.name) {
+                error(format!("Function {} declared twice", function.name), token.pos);
+            }
+            map.insert(function.name.clone(), function.clone());
+        }
+        map
+    })
+}
+
+pub fn check_matches(tokens: &[Token], enums: &HashMap<String, EnumType>) {
+    tokens.iter().for_each(|token| {
+        if let TokenType::Match(name, arms) = &token.token {
+            check_match(name, arms, enums, token.pos);
+            arms.iter().for_each(|arm| check_matches(&arm.tokens, enums));
+        } else if let TokenType::Try(tokens, _, catch_tokens) = &token.token {
+            check_matches(tokens, enums);
+            check_matches(catch_tokens, enums);
+        } else if let TokenType::Function(function) = &token.token {
+            check_matches(&function.tokens, enums);
+        }
+    });
+}
+
+fn check_match(name: &String, arms: &Vec<MatchArm>, enums: &HashMap<String, EnumType>, pos: Position) {
+    let mut covered: Vec<&str> = vec![];
+    let mut wildcard = false;
+    let mut enum_type: Option<&EnumType> = None;
+    for arm in arms {
+        if wildcard {
+            error(format!("Unreachable match arm {} after wildcard", arm.variant), arm.pos);
+        }
+        if arm.variant == "_" {
+            wildcard = true;
+            continue;
+        }
+
+        let (enum_name, variant_name) = arm.variant.split_once("::").unwrap();
+        if let Some(typ) = enum_type {
+            if typ.name != enum_name {
+                error(format!("Match arm {} doesn't belong to enum {}", arm.variant, typ.name), arm.pos);
+            }
+        } else if let Some(typ) = enums.get(enum_name) {
+            enum_type = Some(typ);
+        } else {
+            error(format!("No such enum: {}", enum_name), arm.pos);
+        }
+
+        if let Some(variant) = enum_type.unwrap().variants.iter().find(|variant| variant.name == variant_name) {
+            if covered.contains(&variant_name) {
+                error(format!("Unreachable match arm {} (already covered)", arm.variant), arm.pos);
+            }
+            if variant.fields.len() != arm.bindings.len() {
+                error(format!("Match arm {} binds {} field(s), but the variant has {}", arm.variant, arm.bindings.len(), variant.fields.len()), arm.pos);
+            }
+            covered.push(variant_name);
+        } else {
+            error(format!("Enum {} has no variant {}", enum_name, variant_name), arm.pos);
+        }
+    }
+
+    if let Some(typ) = enum_type {
+        let missing = typ.variants.iter()
+            .filter(|variant| !covered.contains(&variant.name.as_str()))
+            .map(|variant| format!("{}::{}", typ.name, variant.name))
+            .collect::<Vec<String>>();
+        if !wildcard && !missing.is_empty() {
+            error(format!("Non-exhaustive match on {}, missing: {}", name, missing.join(", ")), pos);
+        }
+    }
+}
+
+fn open_scope(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, token) in tokens.iter().enumerate().rev() {
+        match token.token {
+            TokenType::ScopeEnd => depth += 1,
+            TokenType::ScopeStart if depth == 0 => return Some(idx),
+            TokenType::ScopeStart => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+//fn add(a (i32), b (i32)), the header is one instruction after pre_parse
+fn function_header(header: &String, pos: Position) -> Function {
+    let (name, params) = function_call(header).unwrap_or_else(|| {
+        error(format!("Invalid function header {}, expected name(param (type), ...)", header), pos);
+        unreachable!()
+    });
+    let params = params.iter().fold(vec![], |mut params: Vec<(String, String)>, param| {
+        if let [param_name, typ] = param.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            let typ = typ.strip_prefix('(').and_then(|typ| typ.strip_suffix(')')).unwrap_or_else(|| {
+                error(format!("Invalid type {} for parameter {} of function {}", typ, param_name, name), pos);
+                unreachable!()
+            }).to_string();
+            if params.iter().any(|(other, _)| other == param_name) {
+                error(format!("Parameter {} declared twice in function {}", param_name, name), pos);
+            }
+            params.push((param_name.to_string(), typ));
+        } else {
+            error(format!("Invalid parameter {} of function {}, expected name (type)", param, name), pos);
+        }
+        params
+    });
+    Function {
+        name,
+        params,
+        returns: None,
+        tokens: vec![],
+        pos
+    }
+}
+
+fn open_parens(s: &str) -> i32 {
+    s.chars().fold((0, false), |(depth, in_string), c| match c {
+        '"' => (depth, !in_string),
+        '(' if !in_string => (depth + 1, in_string),
+        ')' if !in_string => (depth - 1, in_string),
+        _ => (depth, in_string)
+    }).0
+}
+
+//(q, r) binds the outputs q and r, (result -> total) binds the output result to total
+//Returns true once the closing parenthesis was found
+fn push_binding(bindings: &mut Vec<(String, String)>, instr: &str, pos: Position) -> bool {
+    let closed = instr.ends_with(')');
+    let name = instr.strip_suffix(')').unwrap_or(instr);
+    let name = name.strip_suffix(',').unwrap_or(name);
+    if name == "->" {
+        match bindings.last_mut() {
+            Some(binding) if binding.0 == binding.1 => binding.1 = String::new(),
+            _ => error_str("Missing output name before ->", pos)
+        }
+    } else if name.contains(['(', ')', ',']) || name.contains("->") {
+        error(format!("Invalid output binding {} (separate names with \", \" and renames with \" -> \")", name), pos);
+    } else if !name.is_empty() {
+        match bindings.last_mut() {
+            Some(binding) if binding.1.is_empty() => binding.1 = name.to_string(),
+            _ => bindings.push((name.to_string(), name.to_string()))
+        }
+    }
+    if closed && bindings.is_empty() {
+        error_str("Empty output binding", pos);
+    } else if closed && bindings.last().is_some_and(|binding| binding.1.is_empty()) {
+        error_str("Missing variable name after ->", pos);
+    }
+    closed
+}
+
+fn push_param(params: &mut Vec<EventParam>, instr: &str, end: bool, pos: Position) {
+    if let Some(param) = params.last_mut().filter(|param| param.name.is_some() && param.value.is_empty()) {
+        param.value = instr.to_string();
+    } else if instr.ends_with(':') && !instr.starts_with('"') {
+        params.push(EventParam {
+            name: Some(instr.substring(0, instr.len() - 1).to_string()),
+            value: String::new()
+        });
+    } else {
+        params.push(EventParam {
+            name: None,
+            value: instr.to_string()
+        });
+    }
+    if let Some(EventParam { name: Some(name), value }) = params.last() {
+        if end && value.is_empty() {
+            error(format!("Missing value for parameter {}", name), pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{pre_parse, tokenize};
+    use crate::util::catch_error;
+    use crate::util::token::{EventParam, TokenType};
+
+    //The tokens of an OnStart listener with the given body, or the first syntax error
+    fn tokens(body: &str) -> Result<Vec<TokenType>, String> {
+        let lines = format!("OnStart {{\n{}}}\n", body).lines().map(String::from).collect();
+        catch_error(|| tokenize(pre_parse(lines)))
+            .map(|tokens| tokens.into_iter().map(|token| token.token).collect())
+            .map_err(|error| error.message)
+    }
+
+    fn positional(value: &str) -> EventParam {
+        EventParam { name: None, value: value.to_string() }
+    }
+
+    #[test]
+    fn output_bindings() {
+        let expected = TokenType::InitVariableOutputs(vec![(String::from("result"), String::from("total")), (String::from("num1"), String::from("num1"))], String::from("+"), Some(vec![positional("2"), positional("3")]));
+        assert!(tokens("    (result -> total, num1) <- #+ <- 2, 3;\n").unwrap().contains(&expected));
+        let expected = TokenType::InitVariableOutputs(vec![(String::from("time"), String::from("time"))], String::from("Now"), None);
+        assert!(tokens("    (time) <- #Now;\n").unwrap().contains(&expected));
+    }
+
+    #[test]
+    fn invalid_output_bindings() {
+        assert_eq!(tokens("    (a, b) <- x;\n"), Err(String::from("Output bindings need an event call, like (q, r) <- #Event")));
+        assert_eq!(tokens("    () <- #Now;\n"), Err(String::from("Empty output binding")));
+        assert_eq!(tokens("    (result ->) <- #+ <- 1, 2;\n"), Err(String::from("Missing variable name after ->")));
+        assert_eq!(tokens("    (-> total) <- #+ <- 1, 2;\n"), Err(String::from("Missing output name before ->")));
+    }
+}
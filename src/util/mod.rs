@@ -1,3 +1,8 @@
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::panic::{catch_unwind, resume_unwind, set_hook, take_hook, AssertUnwindSafe};
+use std::sync::Once;
 use crate::util::position::Position;
 
 pub mod position;
@@ -6,15 +11,109 @@ pub mod variables;
 pub mod scope;
 pub mod debug;
 
+//Codes of caught errors: 1 for runtime errors, 2 for invalid values (the error helpers)
+pub const RUNTIME_ERROR_CODE: i32 = 1;
+pub const VALUE_ERROR_CODE: i32 = 2;
+
+#[derive(Clone, Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub code: i32,
+    pub pos: Option<Position>
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.pos {
+            Some(pos) => write!(f, "{} (at {})", self.message, pos),
+            None => write!(f, "{} (unknown position)", self.message)
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<RuntimeError>> = const { RefCell::new(None) };
+    //How many catch_error calls this thread is in, their panics aren't printed
+    static CATCHING: Cell<u32> = const { Cell::new(0) };
+}
+
+static QUIET_HOOK: Once = Once::new();
+
+//Wraps the panic hook once instead of swapping it per call, so panics of other threads and outside catch_error still reach it
+//A hook set after the first catch_error replaces the wrapper
+fn install_quiet_hook() {
+    QUIET_HOOK.call_once(|| {
+        let hook = take_hook();
+        set_hook(Box::new(move |info| {
+            if CATCHING.try_with(|catching| catching.get()).unwrap_or(0) == 0 {
+                hook(info);
+            }
+        }));
+    });
+}
+
+fn set_last_error(msg: &str, code: i32, pos: Option<Position>) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(RuntimeError {
+        message: msg.to_string(),
+        code,
+        pos
+    }));
+}
+
+//Unwinds out of the running listener without an error, once Exit stopped the VM in the middle of an instruction
+pub struct Halt;
+
+pub fn halt() -> ! {
+    resume_unwind(Box::new(Halt))
+}
+
+//Runs f, None if it halted
+pub fn catch_halt<T, F: FnOnce() -> T>(f: F) -> Option<T> {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => Some(value),
+        Err(payload) if payload.is::<Halt>() => None,
+        Err(payload) => resume_unwind(payload)
+    }
+}
+
+//Runs f and turns a failure (any of the error helpers panicking) into a RuntimeError, without printing it
+//A halt isn't an error, it goes on to catch_halt
+pub fn catch_error<T, F: FnOnce() -> T>(f: F) -> Result<T, RuntimeError> {
+    install_quiet_hook();
+    LAST_ERROR.with(|last| last.borrow_mut().take());
+    CATCHING.with(|catching| catching.set(catching.get() + 1));
+    let result = catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(catching.get() - 1));
+    result.map_err(|payload| if payload.is::<Halt>() {
+        resume_unwind(payload)
+    } else {
+        payload
+    }).map_err(|payload| LAST_ERROR.with(|last| last.borrow_mut().take()).unwrap_or_else(|| {
+        let message = payload.downcast_ref::<String>().cloned()
+            .or_else(|| payload.downcast_ref::<&str>().map(|msg| msg.to_string()))
+            .unwrap_or(String::from("No message provided"));
+        RuntimeError {
+            message,
+            code: RUNTIME_ERROR_CODE,
+            pos: None
+        }
+    }))
+}
+
 pub fn error(msg: String, pos: Position) {
+    set_last_error(&msg, VALUE_ERROR_CODE, Some(pos));
     panic!("ERROR: {} (at {})", msg, pos);
 }
 
 pub fn error_unknown_pos(msg: String) {
+    set_last_error(&msg, VALUE_ERROR_CODE, None);
     panic!("ERROR: {} (unknown position)", msg);
 }
 
 pub fn error_str(msg: &str, pos: Position) {
+    set_last_error(msg, VALUE_ERROR_CODE, Some(pos));
     panic!("ERROR: {} (at {})", msg, pos);
 }
 
@@ -27,13 +126,16 @@ pub fn warning_str(msg: &str, pos: Position) {
 }
 
 pub fn runtime_error_str(msg: &str, pos: Position) {
+    set_last_error(msg, RUNTIME_ERROR_CODE, Some(pos));
     panic!("RUNTIME ERROR: {} (at {})", msg, pos);
 }
 
 pub fn runtime_error(msg: String, pos: Position) {
+    set_last_error(&msg, RUNTIME_ERROR_CODE, Some(pos));
     panic!("RUNTIME ERROR: {} (at {})", msg, pos);
 }
 
 pub fn runtime_error_unknown_pos(msg: String) {
+    set_last_error(&msg, RUNTIME_ERROR_CODE, None);
     panic!("RUNTIME ERROR: {} (unknown position)", msg);
 }
\ No newline at end of file
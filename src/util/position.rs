@@ -1,6 +1,6 @@
 use std::fmt::{Display, Formatter};
 
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Default, Copy, Clone, PartialEq, Debug)]
 pub struct Position {
     pub(crate) line: u16,
     pub(crate) index: u16
@@ -1,6 +1,26 @@
+use std::collections::HashMap;
 use crate::util::variables::Variable;
 
+//Variables are hashed by name, a name can only exist once per scope
 #[derive(Default,Clone,Debug)]
 pub struct Scope {
-    pub stack: Vec<Variable>
-}
\ No newline at end of file
+    pub vars: HashMap<String, Variable>
+}
+
+impl Scope {
+    pub fn declare(&mut self, var: Variable) {
+        self.vars.insert(var.name.clone(), var);
+    }
+
+    pub fn get(&self, name: &String) -> Option<&Variable> {
+        self.vars.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &String) -> Option<&mut Variable> {
+        self.vars.get_mut(name)
+    }
+
+    pub fn contains(&self, name: &String) -> bool {
+        self.vars.contains_key(name)
+    }
+}
@@ -1,6 +1,7 @@
 use crate::util::position::Position;
+use crate::util::variables::CastMode;
 
-#[derive(Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct Token {
     pub token: TokenType,
     pub pos: Position
@@ -10,17 +11,62 @@ pub struct Token {
 pub enum TokenType {
     Keyword(Keyword),
     Listener(String),
-    CallEvent(String, Option<Vec<String>>),
+    ListenerPriority(i32),
+    CallEvent(String, Option<Vec<EventParam>>),
+    QueueEvent(String, Option<Vec<EventParam>>),
     ScopeStart,
     ScopeEnd,
     Raw(String),
-    InitVariable(String, String, bool, bool, Option<String>),
-    InitVariableEvent(String, String, Option<Vec<String>>),
+    InitVariable(String, String, bool, Option<CastMode>, Option<String>),
+    InitVariableEvent(String, String, Option<Vec<EventParam>>),
+    InitVariableEnum(String, String, String, Option<Vec<EventParam>>),
+    //(output, variable) pairs and if the closing parenthesis was found yet
+    OutputBinding(Vec<(String, String)>, bool),
+    InitVariableOutputs(Vec<(String, String)>, String, Option<Vec<EventParam>>),
     VariableStaticSet(String, String),
-    VariableDynamicSet(String, String, bool),
-    VariableEventSet(String, String, Option<Vec<String>>)
+    VariableDynamicSet(String, String, Option<CastMode>),
+    VariableEventSet(String, String, Option<Vec<EventParam>>),
+    VariableEnumSet(String, String, Option<Vec<EventParam>>),
+    EnumVariant(String, Vec<(String, String)>),
+    Match(String, Vec<MatchArm>),
+    MatchArm(MatchArm),
+    //try body, name of the error variable in catch, catch body
+    Try(Vec<Token>, Option<String>, Vec<Token>),
+    Function(Function),
+    //function name and the unparsed arguments
+    CallFunction(String, Vec<String>),
+    Return(Option<String>)
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct EventParam {
+    pub name: Option<String>,
+    pub value: String
 }
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Keyword {
-}
\ No newline at end of file
+    Match,
+    Try,
+    Catch,
+    Fn,
+    Return
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct MatchArm {
+    pub variant: String,
+    pub bindings: Vec<String>,
+    pub tokens: Vec<Token>,
+    pub pos: Position
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    //(name, type)
+    pub params: Vec<(String, String)>,
+    pub returns: Option<String>,
+    pub tokens: Vec<Token>,
+    pub pos: Position
+}
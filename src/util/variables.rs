@@ -1,6 +1,6 @@
-use std::any::Any;
 use std::str::FromStr;
 use substring::Substring;
+use crate::event::schema::{EventField, EventSchema};
 use crate::event::standard_events::Event;
 use crate::util::{error, error_str, runtime_error, runtime_error_unknown_pos};
 use crate::util::position::Position;
@@ -11,6 +11,7 @@ pub enum VariableType {
     U16(Option<u16>),
     U32(Option<u32>),
     U64(Option<u64>),
+    U128(Option<u128>),
     I8(Option<i8>),
     I16(Option<i16>),
     I32(Option<i32>),
@@ -21,7 +22,61 @@ pub enum VariableType {
     Bool(Option<bool>),
     Float(Option<f32>),
     Double(Option<f64>),
-    Event(Box<dyn Event>)
+    Event(Box<dyn Event>),
+    Enum(String, Option<EnumValue>)
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumValue {
+    pub variant: String,
+    pub payload: Vec<Variable>
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumType {
+    pub name: String,
+    pub variants: Vec<EnumVariant>
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumVariant {
+    pub name: String,
+    pub fields: Vec<(String, String)>
+}
+
+impl EnumVariant {
+    pub fn schema(&self, enum_name: &String) -> EventSchema {
+        self.fields.iter().fold(EventSchema::new(format!("{}::{}", enum_name, self.name).as_str(), ""), |schema, (name, typ)| {
+            schema.param(EventField::new(name, typ))
+        })
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CastMode {
+    Checked,
+    Truncating,
+    Saturating
+}
+
+impl CastMode {
+    pub fn from_operator(operator: &str) -> Option<CastMode> {
+        match operator {
+            "<=" => Some(CastMode::Checked),
+            "<=%" => Some(CastMode::Truncating),
+            "<=|" => Some(CastMode::Saturating),
+            _ => None
+        }
+    }
+}
+
+enum CastValue {
+    Signed(i128),
+    Unsigned(u128),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String)
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +86,43 @@ pub struct Variable {
     pub nullable: bool
 }
 
+macro_rules! cast_int {
+    ($value:expr,$mode:expr,$pos:expr,$typ_literal:expr,$typ:ty) => {{
+        match cast_number($value, &$typ_literal, $pos) {
+            CastValue::Signed(val) => match $mode {
+                CastMode::Checked => Some(<$typ>::try_from(val).unwrap_or_else(|_| {
+                    runtime_error(format!("Can't cast {} to {}: out of range", val, $typ_literal), $pos);
+                    unreachable!()
+                })),
+                CastMode::Truncating => Some(val as $typ),
+                CastMode::Saturating => Some(<$typ>::try_from(val).unwrap_or(if val < 0 { <$typ>::MIN } else { <$typ>::MAX }))
+            },
+            CastValue::Unsigned(val) => match $mode {
+                CastMode::Checked => Some(<$typ>::try_from(val).unwrap_or_else(|_| {
+                    runtime_error(format!("Can't cast {} to {}: out of range", val, $typ_literal), $pos);
+                    unreachable!()
+                })),
+                CastMode::Truncating => Some(val as $typ),
+                CastMode::Saturating => Some(<$typ>::try_from(val).unwrap_or(<$typ>::MAX))
+            },
+            CastValue::Float(val) => match $mode {
+                CastMode::Checked => {
+                    let val = val.trunc();
+                    if val.is_finite() && val >= <$typ>::MIN as f64 && val < <$typ>::MAX as f64 + 1.0 {
+                        Some(val as $typ)
+                    } else {
+                        runtime_error(format!("Can't cast {} to {}: out of range", val, $typ_literal), $pos);
+                        unreachable!()
+                    }
+                }
+                CastMode::Truncating => Some(val as i128 as $typ),
+                CastMode::Saturating => Some(val as $typ)
+            },
+            _ => unreachable!()
+        }
+    }}
+}
+
 macro_rules! var_type {
     ($value:expr,$pos:expr,$typ_literal:expr,$typ:ty) => {{
         if let Ok(value) = <$typ>::from_str($value.as_str()) {
@@ -42,6 +134,38 @@ macro_rules! var_type {
     }}
 }
 
+macro_rules! from_value {
+    ($typ:ty,$variant:ident) => {
+        impl From<$typ> for VariableType {
+            fn from(value: $typ) -> Self {
+                VariableType::$variant(Some(value))
+            }
+        }
+    }
+}
+
+from_value!(u8, U8);
+from_value!(u16, U16);
+from_value!(u32, U32);
+from_value!(u64, U64);
+from_value!(u128, U128);
+from_value!(i8, I8);
+from_value!(i16, I16);
+from_value!(i32, I32);
+from_value!(i64, I64);
+from_value!(i128, I128);
+from_value!(char, Char);
+from_value!(String, String);
+from_value!(bool, Bool);
+from_value!(f32, Float);
+from_value!(f64, Double);
+
+impl From<&str> for VariableType {
+    fn from(value: &str) -> Self {
+        VariableType::String(Some(value.to_string()))
+    }
+}
+
 impl Variable {
     pub fn new(name: &String, typ: &String, value: &String, pos: Position) -> Self {
         let mut typ = typ.clone();
@@ -74,14 +198,28 @@ impl Variable {
         }
     }
 
-    pub fn new_type(name: &String, typ: &String, cast: bool, value: VariableType, pos: Position) -> Self {
-        let mut typ = typ.clone();
+    pub fn new_enum(name: &String, typ: &str, value: VariableType, pos: Position) -> Self {
+        let mut typ = typ.to_string();
+        let nullable = typ.starts_with('?');
+        if nullable {
+            typ.remove(0);
+        }
+        check_enum_value(name, &typ, nullable, &value, pos);
+
+        Variable {
+            name: name.clone(),
+            variable_type: value,
+            nullable
+        }
+    }
+
+    pub fn new_type(name: &String, typ: &str, cast: Option<CastMode>, value: VariableType, pos: Position) -> Self {
+        let mut typ = typ.to_string();
         let nullable = typ.starts_with('?');
         if nullable {
             typ.remove(0);
         }
-        let string_value = type_value_to_string(&value);
-        let set_null = string_value.is_none();
+        let set_null = type_value_to_string(&value).is_none();
         if set_null && !nullable {
             runtime_error(format!("Can't assign null to non-null variable {}", name), pos);
         }
@@ -92,103 +230,275 @@ impl Variable {
                 variable_type: value,
                 nullable
             };
-        } else if cast {
-            let variable_type = if set_null {
-                name_to_type_null(&typ, pos)
-            } else {
-                name_to_type(&typ, &string_value.unwrap(), pos)
-            };
+        } else if let Some(mode) = cast {
             return Variable {
                 name: name.clone(),
-                variable_type,
+                variable_type: cast_value(value, &variable_type, mode, pos),
                 nullable
             };
         } else {
-            runtime_error(format!("Can't assign type {} to variable {} of type {}", type_to_name(value), name, typ), pos);
+            runtime_error(format!("Can't assign to variable {}: expected {}, found {}", name, typ, type_to_name(&value)), pos);
             unreachable!();
         }
     }
 
-    pub fn copy(var: Variable, value: &String, pos: Position) -> Self {
+    pub fn copy(var: &Variable, value: &String, pos: Position) -> Self {
         let set_null = value == "null";
         if set_null && !var.nullable {
             runtime_error(format!("Can't assign null to non-null variable {}", var.name), pos);
         }
 
         let variable_type = if set_null {
-            clone_type_null(var.variable_type, pos)
+            clone_type_null(var.variable_type.clone(), pos)
         } else {
-            clone_type(var.variable_type, value, pos)
+            clone_type(var.variable_type.clone(), value, pos)
         };
 
         Variable {
-            name: var.name,
+            name: var.name.clone(),
             variable_type,
             nullable: var.nullable
         }
     }
 
-    pub fn copy_type(var: Variable, cast: bool, value: VariableType, pos: Position) -> Self {
-        let string_value = type_value_to_string(&value);
-        let set_null = string_value.is_none();
+    pub fn copy_enum(var: &Variable, value: VariableType, pos: Position) -> Self {
+        if let VariableType::Enum(typ, _) = &var.variable_type {
+            check_enum_value(&var.name, typ, var.nullable, &value, pos);
+        } else {
+            runtime_error(format!("Can't assign to variable {}: expected {}, found {}", var.name, type_to_name(&var.variable_type), type_to_name(&value)), pos);
+        }
+
+        Variable {
+            name: var.name.clone(),
+            variable_type: value,
+            nullable: var.nullable
+        }
+    }
+
+    pub fn copy_type(var: &Variable, cast: Option<CastMode>, value: VariableType, pos: Position) -> Self {
+        let set_null = type_value_to_string(&value).is_none();
         if set_null && !var.nullable {
             runtime_error(format!("Can't assign null to non-null variable {}", var.name), pos);
         }
-        if var.variable_type.type_id() == value.type_id() {
+        if check_same_type(&var.variable_type, &value) {
             return Variable {
                 name: var.name.clone(),
                 variable_type: value,
                 nullable: var.nullable
             };
-        } else if cast {
-            let variable_type = if set_null {
-                name_to_type_null(&type_to_name(var.variable_type).to_string(), pos)
-            } else {
-                name_to_type(&type_to_name(var.variable_type).to_string(), &string_value.unwrap(), pos)
-            };
+        } else if let Some(mode) = cast {
             return Variable {
                 name: var.name.clone(),
-                variable_type,
+                variable_type: cast_value(value, &var.variable_type, mode, pos),
                 nullable: var.nullable
             };
         } else {
-            runtime_error(format!("Can't assign type {} to variable {} of type {}", type_to_name(value), var.name, type_to_name(var.variable_type)), pos);
+            runtime_error(format!("Can't assign to variable {}: expected {}, found {}", var.name, type_to_name(&var.variable_type), type_to_name(&value)), pos);
             unreachable!();
         }
     }
 }
 
-fn type_to_name(typ: VariableType) -> &'static str {
+// Cast matrix (see the syntax file for the script side):
+// - integer -> integer: Checked fails when out of range, Truncating wraps, Saturating clamps
+// - float/double -> integer: the fraction is dropped, out of range values behave as above (NaN saturates to 0)
+// - bool -> number is 0 or 1, number -> bool is only 0 or 1 when Checked and "not 0" otherwise
+// - char <-> integer uses the unicode code point, invalid code points always fail
+// - anything -> string formats the value, string -> anything parses it (numbers may be floats like "3.7")
+// - bool <-> char, events and enums can't be cast (can_cast)
+fn cast_value(value: VariableType, target: &VariableType, mode: CastMode, pos: Position) -> VariableType {
+    if check_same_type(target, &value) {
+        return value;
+    }
+    let from = type_to_name(&value);
+    let to = type_to_name(target);
+    if !can_cast(&value, target) {
+        runtime_error(format!("Can't cast {} to {}", from, to), pos);
+    }
+    if type_value_to_string(&value).is_none() {
+        return clone_type_null(target.clone(), pos);
+    }
+
+    let value = match value {
+        VariableType::U8(val) => CastValue::Unsigned(val.unwrap() as u128),
+        VariableType::U16(val) => CastValue::Unsigned(val.unwrap() as u128),
+        VariableType::U32(val) => CastValue::Unsigned(val.unwrap() as u128),
+        VariableType::U64(val) => CastValue::Unsigned(val.unwrap() as u128),
+        VariableType::U128(val) => CastValue::Unsigned(val.unwrap()),
+        VariableType::I8(val) => CastValue::Signed(val.unwrap() as i128),
+        VariableType::I16(val) => CastValue::Signed(val.unwrap() as i128),
+        VariableType::I32(val) => CastValue::Signed(val.unwrap() as i128),
+        VariableType::I64(val) => CastValue::Signed(val.unwrap() as i128),
+        VariableType::I128(val) => CastValue::Signed(val.unwrap()),
+        VariableType::Char(val) => CastValue::Char(val.unwrap()),
+        VariableType::String(val) => CastValue::String(val.unwrap()),
+        VariableType::Bool(val) => CastValue::Bool(val.unwrap()),
+        VariableType::Float(val) => CastValue::Float(val.unwrap() as f64),
+        VariableType::Double(val) => CastValue::Float(val.unwrap()),
+        VariableType::Event(_) | VariableType::Enum(_, _) => unreachable!()
+    };
+
+    match target {
+        VariableType::U8(_) => VariableType::U8(cast_int!(value,mode,pos,to,u8)),
+        VariableType::U16(_) => VariableType::U16(cast_int!(value,mode,pos,to,u16)),
+        VariableType::U32(_) => VariableType::U32(cast_int!(value,mode,pos,to,u32)),
+        VariableType::U64(_) => VariableType::U64(cast_int!(value,mode,pos,to,u64)),
+        VariableType::U128(_) => VariableType::U128(cast_int!(value,mode,pos,to,u128)),
+        VariableType::I8(_) => VariableType::I8(cast_int!(value,mode,pos,to,i8)),
+        VariableType::I16(_) => VariableType::I16(cast_int!(value,mode,pos,to,i16)),
+        VariableType::I32(_) => VariableType::I32(cast_int!(value,mode,pos,to,i32)),
+        VariableType::I64(_) => VariableType::I64(cast_int!(value,mode,pos,to,i64)),
+        VariableType::I128(_) => VariableType::I128(cast_int!(value,mode,pos,to,i128)),
+        VariableType::Float(_) => {
+            let val = cast_float(value, &to, pos);
+            if mode == CastMode::Checked && val.is_finite() && (val as f32).is_infinite() {
+                runtime_error(format!("Can't cast {} to {}: out of range", val, to), pos);
+            }
+            if mode == CastMode::Saturating {
+                VariableType::Float(Some(val.clamp(f32::MIN as f64, f32::MAX as f64) as f32))
+            } else {
+                VariableType::Float(Some(val as f32))
+            }
+        }
+        VariableType::Double(_) => VariableType::Double(Some(cast_float(value, &to, pos))),
+        VariableType::Bool(_) => {
+            let val = match value {
+                CastValue::Char(_) => unreachable!(),
+                CastValue::String(val) => bool::from_str(val.trim()).unwrap_or_else(|_| {
+                    runtime_error(format!(r#"Can't cast "{}" to {}: expected true or false"#, val, to), pos);
+                    unreachable!()
+                }),
+                value => {
+                    let val = cast_float(value, &to, pos);
+                    if mode == CastMode::Checked && val != 0.0 && val != 1.0 {
+                        runtime_error(format!("Can't cast {} to {}: only 0 and 1 are valid", val, to), pos);
+                    }
+                    val != 0.0
+                }
+            };
+            VariableType::Bool(Some(val))
+        }
+        VariableType::Char(_) => {
+            let val = match value {
+                CastValue::Bool(_) => unreachable!(),
+                CastValue::Char(val) => val,
+                CastValue::String(val) if val.chars().count() == 1 => val.chars().next().unwrap(),
+                CastValue::String(val) => {
+                    runtime_error(format!(r#"Can't cast "{}" to {}: expected exactly one character"#, val, to), pos);
+                    unreachable!()
+                }
+                value => {
+                    let code = cast_int!(value,mode,pos,to,u32).unwrap();
+                    char::from_u32(code).unwrap_or_else(|| {
+                        runtime_error(format!("Can't cast {} to {}: invalid code point", code, to), pos);
+                        unreachable!()
+                    })
+                }
+            };
+            VariableType::Char(Some(val))
+        }
+        VariableType::String(_) => VariableType::String(Some(match value {
+            CastValue::Signed(val) => val.to_string(),
+            CastValue::Unsigned(val) => val.to_string(),
+            CastValue::Float(val) if from == "float" => (val as f32).to_string(),
+            CastValue::Float(val) => val.to_string(),
+            CastValue::Bool(val) => val.to_string(),
+            CastValue::Char(val) => val.to_string(),
+            CastValue::String(val) => val
+        })),
+        VariableType::Event(_) | VariableType::Enum(_, _) => unreachable!()
+    }
+}
+
+//Casts between these types fail for every value, the checker rejects them before running
+pub fn can_cast(from: &VariableType, to: &VariableType) -> bool {
+    !matches!((from, to),
+        (VariableType::Event(_) | VariableType::Enum(_, _), _)
+        | (_, VariableType::Event(_) | VariableType::Enum(_, _))
+        | (VariableType::Bool(_), VariableType::Char(_))
+        | (VariableType::Char(_), VariableType::Bool(_)))
+}
+
+fn cast_number(value: CastValue, to: &str, pos: Position) -> CastValue {
+    match value {
+        CastValue::Bool(val) => CastValue::Unsigned(val as u128),
+        CastValue::Char(val) => CastValue::Unsigned(val as u128),
+        CastValue::String(val) => {
+            let trimmed = val.trim();
+            if let Ok(val) = i128::from_str(trimmed) {
+                CastValue::Signed(val)
+            } else if let Ok(val) = u128::from_str(trimmed) {
+                CastValue::Unsigned(val)
+            } else if let Ok(val) = f64::from_str(trimmed) {
+                CastValue::Float(val)
+            } else {
+                runtime_error(format!(r#"Can't cast "{}" to {}: not a number"#, val, to), pos);
+                unreachable!()
+            }
+        }
+        value => value
+    }
+}
+
+fn cast_float(value: CastValue, to: &str, pos: Position) -> f64 {
+    match cast_number(value, to, pos) {
+        CastValue::Signed(val) => val as f64,
+        CastValue::Unsigned(val) => val as f64,
+        CastValue::Float(val) => val,
+        _ => unreachable!()
+    }
+}
+
+fn check_enum_value(name: &String, typ: &String, nullable: bool, value: &VariableType, pos: Position) {
+    if let VariableType::Enum(enum_name, enum_value) = value {
+        if enum_name != typ {
+            runtime_error(format!("Can't assign to variable {}: expected {}, found {}", name, typ, enum_name), pos);
+        }
+        if enum_value.is_none() && !nullable {
+            runtime_error(format!("Can't assign null to non-null variable {}", name), pos);
+        }
+    } else {
+        runtime_error(format!("Can't assign to variable {}: expected {}, found {}", name, typ, type_to_name(value)), pos);
+    }
+}
+
+pub fn type_to_name(typ: &VariableType) -> String {
     match typ {
-        VariableType::U8(_) => "u8",
-        VariableType::U16(_) => "u16",
-        VariableType::U32(_) => "u32",
-        VariableType::U64(_) => "u64",
-        VariableType::I8(_) => "i8",
-        VariableType::I16(_) => "i16",
-        VariableType::I32(_) => "i32",
-        VariableType::I64(_) => "i64",
-        VariableType::I128(_) => "i128",
-        VariableType::Char(_) => "char",
-        VariableType::String(_) => "string",
-        VariableType::Bool(_) => "bool",
-        VariableType::Float(_) => "float",
-        VariableType::Double(_) => "double",
-        VariableType::Event(_) => "event"
+        VariableType::U8(_) => String::from("u8"),
+        VariableType::U16(_) => String::from("u16"),
+        VariableType::U32(_) => String::from("u32"),
+        VariableType::U64(_) => String::from("u64"),
+        VariableType::U128(_) => String::from("u128"),
+        VariableType::I8(_) => String::from("i8"),
+        VariableType::I16(_) => String::from("i16"),
+        VariableType::I32(_) => String::from("i32"),
+        VariableType::I64(_) => String::from("i64"),
+        VariableType::I128(_) => String::from("i128"),
+        VariableType::Char(_) => String::from("char"),
+        VariableType::String(_) => String::from("string"),
+        VariableType::Bool(_) => String::from("bool"),
+        VariableType::Float(_) => String::from("float"),
+        VariableType::Double(_) => String::from("double"),
+        VariableType::Event(_) => String::from("event"),
+        VariableType::Enum(name, _) => name.clone()
     }
 }
 
 pub fn type_value_to_number(value: &VariableType) -> Option<i128> {
     match value {
-        VariableType::U8(val) => if let Some(val) = val { Some(*val as i128) } else { None },
-        VariableType::U16(val) => if let Some(val) = val { Some(*val as i128) } else { None },
-        VariableType::U32(val) => if let Some(val) = val { Some(*val as i128) } else { None },
-        VariableType::U64(val) => if let Some(val) = val { Some(*val as i128) } else { None },
-        VariableType::I8(val) => if let Some(val) = val { Some(*val as i128) } else { None },
-        VariableType::I16(val) => if let Some(val) = val { Some(*val as i128) } else { None },
-        VariableType::I32(val) => if let Some(val) = val { Some(*val as i128) } else { None },
-        VariableType::I64(val) => if let Some(val) = val { Some(*val as i128) } else { None },
-        VariableType::I128(val) => if let Some(val) = val { Some(*val) } else { None },
+        VariableType::U8(val) => val.map(|val| val as i128),
+        VariableType::U16(val) => val.map(|val| val as i128),
+        VariableType::U32(val) => val.map(|val| val as i128),
+        VariableType::U64(val) => val.map(|val| val as i128),
+        VariableType::U128(val) => val.map(|val| i128::try_from(val).unwrap_or_else(|_| {
+            runtime_error_unknown_pos(format!("u128 value {} is too large to be used as a number", val));
+            unreachable!();
+        })),
+        VariableType::I8(val) => val.map(|val| val as i128),
+        VariableType::I16(val) => val.map(|val| val as i128),
+        VariableType::I32(val) => val.map(|val| val as i128),
+        VariableType::I64(val) => val.map(|val| val as i128),
+        VariableType::I128(val) => *val,
         _ => {
             runtime_error_unknown_pos(format!("Can't get variable type {:?} as a number", value));
             unreachable!();
@@ -198,38 +508,57 @@ pub fn type_value_to_number(value: &VariableType) -> Option<i128> {
 
 pub fn type_value_to_string(value: &VariableType) -> Option<String> {
     match value {
-        VariableType::U8(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::U16(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::U32(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::U64(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::I8(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::I16(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::I32(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::I64(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::I128(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::Char(val) => if let Some(val) = val { Some(String::from(val.clone())) } else { None },
-        VariableType::String(val) => if let Some(val) = val { Some(val.clone()) } else { None },
-        VariableType::Bool(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None }
-        VariableType::Float(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::Double(val) => if let Some(val) = val { Some(format!("{}", val)) } else { None },
-        VariableType::Event(val) => Some(val.name().to_string())
+        VariableType::U8(val) => val.map(|val| format!("{}", val)),
+        VariableType::U16(val) => val.map(|val| format!("{}", val)),
+        VariableType::U32(val) => val.map(|val| format!("{}", val)),
+        VariableType::U64(val) => val.map(|val| format!("{}", val)),
+        VariableType::U128(val) => val.map(|val| format!("{}", val)),
+        VariableType::I8(val) => val.map(|val| format!("{}", val)),
+        VariableType::I16(val) => val.map(|val| format!("{}", val)),
+        VariableType::I32(val) => val.map(|val| format!("{}", val)),
+        VariableType::I64(val) => val.map(|val| format!("{}", val)),
+        VariableType::I128(val) => val.map(|val| format!("{}", val)),
+        VariableType::Char(val) => val.map(String::from),
+        VariableType::String(val) => val.clone(),
+        VariableType::Bool(val) => val.map(|val| format!("{}", val)),
+        VariableType::Float(val) => val.map(|val| format!("{}", val)),
+        VariableType::Double(val) => val.map(|val| format!("{}", val)),
+        VariableType::Event(val) => Some(val.name().to_string()),
+        VariableType::Enum(name, val) => val.as_ref().map(|val| enum_value_to_string(name, val))
+    }
+}
+
+fn enum_value_to_string(name: &String, value: &EnumValue) -> String {
+    if value.payload.is_empty() {
+        format!("{}::{}", name, value.variant)
+    } else {
+        let payload = value.payload.iter()
+            .map(|var| type_value_to_string(&var.variable_type).unwrap_or(String::from("null")))
+            .collect::<Vec<String>>();
+        format!("{}::{}({})", name, value.variant, payload.join(", "))
     }
 }
 
+pub fn check_type_name(name: &str, pos: Position) {
+    let mut name = name.to_string();
+    if name.starts_with('?') {
+        name.remove(0);
+    }
+    name_to_type_null(&name, pos);
+}
+
 fn name_to_type(name: &String, value: &String, pos: Position) -> VariableType {
     match name.as_str() {
         "u8" => VariableType::U8(var_type!(value,pos,name,u8)),
         "u16" => VariableType::U16(var_type!(value,pos,name,u16)),
         "u32" => VariableType::U32(var_type!(value,pos,name,u32)),
         "u64" => VariableType::U64(var_type!(value,pos,name,u64)),
+        "u128" => VariableType::U128(var_type!(value,pos,name,u128)),
         "i8" => VariableType::I8(var_type!(value,pos,name,i8)),
         "i16" => VariableType::I16(var_type!(value,pos,name,i16)),
         "i32" => VariableType::I32(var_type!(value,pos,name,i32)),
         "i64" => VariableType::I64(var_type!(value,pos,name,i64)),
-        "i128" => {
-            error_str("Can't directly initialize i128 type", pos);
-            unreachable!()
-        },
+        "i128" => VariableType::I128(var_type!(value,pos,name,i128)),
         "char" => VariableType::Char(var_type!(value,pos,name,char)),
         "string" => {
             if value.starts_with('"') && value.ends_with('"') {
@@ -253,33 +582,132 @@ fn name_to_type(name: &String, value: &String, pos: Position) -> VariableType {
     }
 }
 
-fn name_to_type_null(name: &String, pos: Position) -> VariableType {
-    match name.as_str() {
-        "u8" => VariableType::U8(None),
-        "u16" => VariableType::U16(None),
-        "u32" => VariableType::U32(None),
-        "u64" => VariableType::U64(None),
-        "i8" => VariableType::I8(None),
-        "i16" => VariableType::I16(None),
-        "i32" => VariableType::I32(None),
-        "i64" => VariableType::I64(None),
-        "i128" => {
-            error_str("Can't directly initialize i128 type", pos);
-            unreachable!()
-        },
-        "char" => VariableType::Char(None),
-        "string" => VariableType::String(None),
-        "bool" => VariableType::Bool(None),
-        "float" => VariableType::Float(None),
-        "double" => VariableType::Double(None),
-        "event" => {
-            error_str("Can't directly initialize event type", pos);
-            unreachable!();
+pub fn is_number(value: &VariableType) -> bool {
+    matches!(value, VariableType::U8(_) | VariableType::U16(_) | VariableType::U32(_) | VariableType::U64(_) | VariableType::U128(_)
+        | VariableType::I8(_) | VariableType::I16(_) | VariableType::I32(_) | VariableType::I64(_) | VariableType::I128(_))
+}
+
+//Number params are i128, so u128 values above i128::MAX are rejected when they are passed
+pub fn fits_number(value: &VariableType) -> bool {
+    match value {
+        VariableType::U128(Some(val)) => i128::try_from(*val).is_ok(),
+        value => is_number(value)
+    }
+}
+
+//sum.result -> (sum, result), strings and numbers like 1.5 are no field access
+pub fn field_access(source: &str) -> Option<(String, String)> {
+    if source.starts_with('"') {
+        return None;
+    }
+    let (var, field) = source.split_once('.')?;
+    if var.is_empty() || field.is_empty() || var.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        None
+    } else {
+        Some((var.to_string(), field.to_string()))
+    }
+}
+
+//add(1, x) -> (add, [1, x]), commas in strings and nested calls don't split the arguments
+pub fn function_call(source: &str) -> Option<(String, Vec<String>)> {
+    let (name, rest) = source.split_once('(')?;
+    let args = rest.strip_suffix(')')?;
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    let (mut args, last, _, _) = args.chars().fold((vec![], String::new(), 0, false), |(mut args, mut arg, mut depth, mut in_string), c| {
+        match c {
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                args.push(arg.trim().to_string());
+                return (args, String::new(), depth, in_string);
+            }
+            _ => {}
         }
-        _ => {
-            error(format!("Invalid Variable Type: {}", name), pos);
-            unreachable!()
+        arg.push(c);
+        (args, arg, depth, in_string)
+    });
+    if !args.is_empty() || !last.trim().is_empty() {
+        args.push(last.trim().to_string());
+    }
+    Some((name.to_string(), args))
+}
+
+pub fn is_literal_of_type(name: &str, value: &str) -> bool {
+    match name {
+        "u8" => u8::from_str(value).is_ok(),
+        "u16" => u16::from_str(value).is_ok(),
+        "u32" => u32::from_str(value).is_ok(),
+        "u64" => u64::from_str(value).is_ok(),
+        "u128" => u128::from_str(value).is_ok(),
+        "i8" => i8::from_str(value).is_ok(),
+        "i16" => i16::from_str(value).is_ok(),
+        "i32" => i32::from_str(value).is_ok(),
+        "i64" => i64::from_str(value).is_ok(),
+        "i128" => i128::from_str(value).is_ok(),
+        "char" => char::from_str(value).is_ok(),
+        "string" => value.len() >= 2 && value.starts_with('"') && value.ends_with('"'),
+        "bool" => bool::from_str(value).is_ok(),
+        "float" => f32::from_str(value).is_ok(),
+        "double" => f64::from_str(value).is_ok(),
+        _ => false
+    }
+}
+
+pub fn literal_value(typ: &str, value: &str) -> Option<VariableType> {
+    match typ.trim_start_matches('?') {
+        "any" | "number" => {
+            if let Ok(value) = i128::from_str(value) {
+                Some(VariableType::I128(Some(value)))
+            } else if typ == "number" {
+                None
+            } else if let Ok(value) = f64::from_str(value) {
+                Some(VariableType::Double(Some(value)))
+            } else if let Ok(value) = bool::from_str(value) {
+                Some(VariableType::Bool(Some(value)))
+            } else if is_literal_of_type("string", value) {
+                Some(VariableType::String(Some(value.substring(1, value.len() - 1).to_string())))
+            } else {
+                None
+            }
         }
+        name if is_literal_of_type(name, value) => Some(name_to_type(&name.to_string(), &value.to_string(), Position::default())),
+        _ => None
+    }
+}
+
+pub fn type_from_name(name: &str) -> Option<VariableType> {
+    match name {
+        "u8" => Some(VariableType::U8(None)),
+        "u16" => Some(VariableType::U16(None)),
+        "u32" => Some(VariableType::U32(None)),
+        "u64" => Some(VariableType::U64(None)),
+        "u128" => Some(VariableType::U128(None)),
+        "i8" => Some(VariableType::I8(None)),
+        "i16" => Some(VariableType::I16(None)),
+        "i32" => Some(VariableType::I32(None)),
+        "i64" => Some(VariableType::I64(None)),
+        "i128" => Some(VariableType::I128(None)),
+        "char" => Some(VariableType::Char(None)),
+        "string" => Some(VariableType::String(None)),
+        "bool" => Some(VariableType::Bool(None)),
+        "float" => Some(VariableType::Float(None)),
+        "double" => Some(VariableType::Double(None)),
+        _ => None
+    }
+}
+
+fn name_to_type_null(name: &String, pos: Position) -> VariableType {
+    if let Some(typ) = type_from_name(name.as_str()) {
+        typ
+    } else if name == "event" {
+        error_str("Can't directly initialize event type", pos);
+        unreachable!()
+    } else {
+        error(format!("Invalid Variable Type: {}", name), pos);
+        unreachable!()
     }
 }
 
@@ -289,14 +717,12 @@ fn clone_type(typ: VariableType, value: &String, pos: Position) -> VariableType
         VariableType::U16(_) => VariableType::U16(var_type!(value,pos,"u16",u16)),
         VariableType::U32(_) => VariableType::U32(var_type!(value,pos,"u32",u32)),
         VariableType::U64(_) => VariableType::U64(var_type!(value,pos,"u64",u64)),
+        VariableType::U128(_) => VariableType::U128(var_type!(value,pos,"u128",u128)),
         VariableType::I8(_) => VariableType::I8(var_type!(value,pos,"i8",i8)),
         VariableType::I16(_) => VariableType::I16(var_type!(value,pos,"i16",i16)),
         VariableType::I32(_) => VariableType::I32(var_type!(value,pos,"i32",i32)),
         VariableType::I64(_) => VariableType::I64(var_type!(value,pos,"i64",i64)),
-        VariableType::I128(_) => {
-            error_str("Can't directly initialize i128 type", pos);
-            unreachable!()
-        },
+        VariableType::I128(_) => VariableType::I128(var_type!(value,pos,"i128",i128)),
         VariableType::Char(_) => VariableType::Char(var_type!(value,pos,"char",char)),
         VariableType::String(_) => {
             if value.starts_with('"') && value.ends_with('"') {
@@ -313,6 +739,10 @@ fn clone_type(typ: VariableType, value: &String, pos: Position) -> VariableType
             error_str("Can't directly initialize event type", pos);
             unreachable!();
         }
+        VariableType::Enum(_, _) => {
+            error_str("Can't directly initialize enum type", pos);
+            unreachable!();
+        }
     }
 }
 
@@ -322,14 +752,12 @@ fn clone_type_null(typ: VariableType, pos: Position) -> VariableType {
         VariableType::U16(_) => VariableType::U16(None),
         VariableType::U32(_) => VariableType::U32(None),
         VariableType::U64(_) => VariableType::U64(None),
+        VariableType::U128(_) => VariableType::U128(None),
         VariableType::I8(_) => VariableType::I8(None),
         VariableType::I16(_) => VariableType::I16(None),
         VariableType::I32(_) => VariableType::I32(None),
         VariableType::I64(_) => VariableType::I64(None),
-        VariableType::I128(_) => {
-            error_str("Can't directly initialize event type", pos);
-            unreachable!();
-        }
+        VariableType::I128(_) => VariableType::I128(None),
         VariableType::Char(_) => VariableType::Char(None),
         VariableType::String(_) => VariableType::String(None),
         VariableType::Bool(_) => VariableType::Bool(None),
@@ -339,25 +767,204 @@ fn clone_type_null(typ: VariableType, pos: Position) -> VariableType {
             error_str("Can't directly initialize event type", pos);
             unreachable!();
         }
+        VariableType::Enum(name, _) => VariableType::Enum(name, None)
     }
 }
 
-fn check_same_type(typ1: &VariableType, typ2: &VariableType) -> bool {
+pub fn check_same_type(typ1: &VariableType, typ2: &VariableType) -> bool {
     match (typ1, typ2) {
         (VariableType::U8(_), VariableType::U8(_)) => true,
         (VariableType::U16(_), VariableType::U16(_)) => true,
         (VariableType::U32(_), VariableType::U32(_)) => true,
         (VariableType::U64(_), VariableType::U64(_)) => true,
+        (VariableType::U128(_), VariableType::U128(_)) => true,
         (VariableType::I8(_), VariableType::I8(_)) => true,
         (VariableType::I16(_), VariableType::I16(_)) => true,
         (VariableType::I32(_), VariableType::I32(_)) => true,
         (VariableType::I64(_), VariableType::I64(_)) => true,
+        (VariableType::I128(_), VariableType::I128(_)) => true,
         (VariableType::Char(_), VariableType::Char(_)) => true,
         (VariableType::String(_), VariableType::String(_)) => true,
         (VariableType::Bool(_), VariableType::Bool(_)) => true,
         (VariableType::Float(_), VariableType::Float(_)) => true,
         (VariableType::Double(_), VariableType::Double(_)) => true,
         (VariableType::Event(_), VariableType::Event(_)) => true,
+        (VariableType::Enum(name1, _), VariableType::Enum(name2, _)) => name1 == name2,
         _ => false
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::catch_error;
+    use crate::util::position::Position;
+    use crate::util::variables::{can_cast, cast_value, field_access, fits_number, type_to_name, type_value_to_string, CastMode, Variable, VariableType};
+
+    fn describe(value: &VariableType) -> String {
+        format!("{} {}", type_to_name(value), type_value_to_string(value).unwrap_or(String::from("null")))
+    }
+
+    //"type value" of the result, or the error message
+    fn cast(value: VariableType, target: VariableType, mode: CastMode) -> Result<String, String> {
+        catch_error(|| cast_value(value, &target, mode, Position::default()))
+            .map(|value| describe(&value))
+            .map_err(|error| error.message)
+    }
+
+    fn declare(typ: &str, value: &str) -> Result<Variable, String> {
+        catch_error(|| Variable::new(&String::from("x"), &typ.to_string(), &value.to_string(), Position::default())).map_err(|error| error.message)
+    }
+
+    #[test]
+    fn wide_integers() {
+        assert_eq!(declare("u128", &u128::MAX.to_string()).map(|var| describe(&var.variable_type)), Ok(format!("u128 {}", u128::MAX)));
+        assert_eq!(declare("i128", &i128::MIN.to_string()).map(|var| describe(&var.variable_type)), Ok(format!("i128 {}", i128::MIN)));
+        assert!(declare("u128", "-1").is_err());
+        assert!(declare("i128", &u128::MAX.to_string()).is_err());
+        assert!(declare("u128", "null").unwrap_err().contains("non-null"));
+
+        let var = declare("?u128", "null").unwrap();
+        assert_eq!(describe(&var.variable_type), "u128 null");
+        let var = Variable::copy(&var, &u128::MAX.to_string(), Position::default());
+        assert_eq!(describe(&var.variable_type), format!("u128 {}", u128::MAX));
+        let var = Variable::copy(&var, &String::from("null"), Position::default());
+        assert_eq!(describe(&var.variable_type), "u128 null");
+
+        let var = declare("i128", "0").unwrap();
+        let var = Variable::copy_type(&var, None, VariableType::I128(Some(-5)), Position::default());
+        assert_eq!(describe(&var.variable_type), "i128 -5");
+        assert!(catch_error(|| Variable::copy_type(&var, None, VariableType::U128(Some(5)), Position::default())).is_err());
+        let var = Variable::copy_type(&var, Some(CastMode::Checked), VariableType::U128(Some(5)), Position::default());
+        assert_eq!(describe(&var.variable_type), "i128 5");
+    }
+
+    #[test]
+    fn wide_integer_casts() {
+        assert!(cast(VariableType::U128(Some(u128::MAX)), VariableType::I128(None), CastMode::Checked).is_err());
+        assert_eq!(cast(VariableType::U128(Some(u128::MAX)), VariableType::I128(None), CastMode::Truncating), Ok(String::from("i128 -1")));
+        assert!(cast(VariableType::I128(Some(-1)), VariableType::U128(None), CastMode::Checked).is_err());
+        assert_eq!(cast(VariableType::I128(Some(-1)), VariableType::U128(None), CastMode::Truncating), Ok(format!("u128 {}", u128::MAX)));
+        assert_eq!(cast(VariableType::I128(Some(-1)), VariableType::U128(None), CastMode::Saturating), Ok(String::from("u128 0")));
+        assert_eq!(cast(VariableType::I128(Some(i128::MAX)), VariableType::U64(None), CastMode::Saturating), Ok(format!("u64 {}", u64::MAX)));
+        assert_eq!(cast(VariableType::U8(Some(7)), VariableType::U128(None), CastMode::Checked), Ok(String::from("u128 7")));
+        assert_eq!(cast(VariableType::String(Some(u128::MAX.to_string())), VariableType::U128(None), CastMode::Checked), Ok(format!("u128 {}", u128::MAX)));
+    }
+
+    #[test]
+    fn wide_integers_as_numbers() {
+        assert!(fits_number(&VariableType::U128(Some(i128::MAX as u128))));
+        assert!(!fits_number(&VariableType::U128(Some(i128::MAX as u128 + 1))));
+        assert!(fits_number(&VariableType::U128(None)));
+        assert!(fits_number(&VariableType::I128(Some(i128::MIN))));
+        assert!(!fits_number(&VariableType::Double(Some(1.0))));
+    }
+
+    #[test]
+    fn integer_modes() {
+        assert_eq!(cast(VariableType::I32(Some(200)), VariableType::U8(None), CastMode::Checked), Ok(String::from("u8 200")));
+        assert!(cast(VariableType::I32(Some(300)), VariableType::U8(None), CastMode::Checked).unwrap_err().contains("out of range"));
+        assert!(cast(VariableType::I32(Some(-1)), VariableType::U8(None), CastMode::Checked).is_err());
+        assert_eq!(cast(VariableType::I32(Some(300)), VariableType::U8(None), CastMode::Truncating), Ok(String::from("u8 44")));
+        assert_eq!(cast(VariableType::I32(Some(-1)), VariableType::U8(None), CastMode::Truncating), Ok(String::from("u8 255")));
+        assert_eq!(cast(VariableType::I32(Some(300)), VariableType::U8(None), CastMode::Saturating), Ok(String::from("u8 255")));
+        assert_eq!(cast(VariableType::I32(Some(-5)), VariableType::U8(None), CastMode::Saturating), Ok(String::from("u8 0")));
+        assert_eq!(cast(VariableType::U128(Some(u128::MAX)), VariableType::I128(None), CastMode::Saturating), Ok(format!("i128 {}", i128::MAX)));
+    }
+
+    #[test]
+    fn float_to_int() {
+        assert_eq!(cast(VariableType::Double(Some(2.9)), VariableType::I32(None), CastMode::Checked), Ok(String::from("i32 2")));
+        assert_eq!(cast(VariableType::Double(Some(-2.9)), VariableType::I32(None), CastMode::Checked), Ok(String::from("i32 -2")));
+        assert!(cast(VariableType::Double(Some(1e10)), VariableType::I32(None), CastMode::Checked).is_err());
+        assert_eq!(cast(VariableType::Double(Some(1e10)), VariableType::I32(None), CastMode::Saturating), Ok(format!("i32 {}", i32::MAX)));
+        assert_eq!(cast(VariableType::Double(Some(f64::NAN)), VariableType::U8(None), CastMode::Saturating), Ok(String::from("u8 0")));
+        assert!(cast(VariableType::Double(Some(f64::NAN)), VariableType::U8(None), CastMode::Checked).is_err());
+    }
+
+    #[test]
+    fn char_code_points() {
+        assert_eq!(cast(VariableType::Char(Some('A')), VariableType::U32(None), CastMode::Checked), Ok(String::from("u32 65")));
+        assert_eq!(cast(VariableType::U8(Some(66)), VariableType::Char(None), CastMode::Checked), Ok(String::from("char B")));
+        assert!(cast(VariableType::U32(Some(0xD800)), VariableType::Char(None), CastMode::Checked).unwrap_err().contains("invalid code point"));
+        assert!(cast(VariableType::Char(Some('Ā')), VariableType::U8(None), CastMode::Checked).is_err());
+        assert_eq!(cast(VariableType::Char(Some('Ā')), VariableType::U8(None), CastMode::Saturating), Ok(String::from("u8 255")));
+    }
+
+    #[test]
+    fn bools() {
+        assert_eq!(cast(VariableType::Bool(Some(true)), VariableType::I8(None), CastMode::Checked), Ok(String::from("i8 1")));
+        assert_eq!(cast(VariableType::I32(Some(1)), VariableType::Bool(None), CastMode::Checked), Ok(String::from("bool true")));
+        assert!(cast(VariableType::I32(Some(2)), VariableType::Bool(None), CastMode::Checked).is_err());
+        assert_eq!(cast(VariableType::I32(Some(2)), VariableType::Bool(None), CastMode::Saturating), Ok(String::from("bool true")));
+        assert!(cast(VariableType::Bool(Some(true)), VariableType::Char(None), CastMode::Checked).is_err());
+        assert!(cast(VariableType::Char(Some('a')), VariableType::Bool(None), CastMode::Saturating).is_err());
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(cast(VariableType::String(Some(String::from(" 42 "))), VariableType::U8(None), CastMode::Checked), Ok(String::from("u8 42")));
+        assert_eq!(cast(VariableType::String(Some(String::from("3.7"))), VariableType::I32(None), CastMode::Checked), Ok(String::from("i32 3")));
+        assert_eq!(cast(VariableType::String(Some(String::from("300"))), VariableType::U8(None), CastMode::Saturating), Ok(String::from("u8 255")));
+        assert!(cast(VariableType::String(Some(String::from("abc"))), VariableType::U8(None), CastMode::Saturating).unwrap_err().contains("not a number"));
+        assert_eq!(cast(VariableType::String(Some(String::from("true"))), VariableType::Bool(None), CastMode::Checked), Ok(String::from("bool true")));
+        assert!(cast(VariableType::String(Some(String::from("ab"))), VariableType::Char(None), CastMode::Checked).is_err());
+        assert_eq!(cast(VariableType::I32(Some(-7)), VariableType::String(None), CastMode::Checked), Ok(String::from("string -7")));
+        assert_eq!(cast(VariableType::Float(Some(1.5)), VariableType::String(None), CastMode::Checked), Ok(String::from("string 1.5")));
+    }
+
+    #[test]
+    fn nulls_and_uncastable_types() {
+        assert_eq!(cast(VariableType::I32(None), VariableType::U8(None), CastMode::Checked), Ok(String::from("u8 null")));
+        assert!(!can_cast(&VariableType::Enum(String::from("S"), None), &VariableType::I32(None)));
+        assert!(!can_cast(&VariableType::I32(None), &VariableType::Enum(String::from("S"), None)));
+        assert!(can_cast(&VariableType::Char(None), &VariableType::Double(None)));
+    }
+
+    #[test]
+    fn field_accesses() {
+        assert_eq!(field_access("sum.result"), Some((String::from("sum"), String::from("result"))));
+        assert_eq!(field_access("this.name"), Some((String::from("this"), String::from("name"))));
+        assert_eq!(field_access("\"a.b\""), None);
+        assert_eq!(field_access("1.5"), None);
+        assert_eq!(field_access("-1.5"), None);
+        assert_eq!(field_access(".x"), None);
+        assert_eq!(field_access("x."), None);
+        assert_eq!(field_access("x"), None);
+    }
+
+    //Copying a value into a variable of its own type keeps the type, any other type needs a cast
+    #[test]
+    fn copies_keep_their_type() {
+        let values = [
+            ("u8", VariableType::U8(Some(1))),
+            ("u16", VariableType::U16(Some(2))),
+            ("u32", VariableType::U32(Some(3))),
+            ("u64", VariableType::U64(Some(4))),
+            ("u128", VariableType::U128(Some(5))),
+            ("i8", VariableType::I8(Some(-1))),
+            ("i16", VariableType::I16(Some(-2))),
+            ("i32", VariableType::I32(Some(-3))),
+            ("i64", VariableType::I64(Some(-4))),
+            ("i128", VariableType::I128(Some(-5))),
+            ("float", VariableType::Float(Some(1.5))),
+            ("double", VariableType::Double(Some(2.5))),
+            ("char", VariableType::Char(Some('c'))),
+            ("string", VariableType::String(Some(String::from("text")))),
+            ("bool", VariableType::Bool(Some(true)))
+        ];
+        for (typ, value) in values {
+            let var = declare(&format!("?{}", typ), "null").unwrap();
+            let expected = describe(&value);
+            let var = Variable::copy_type(&var, None, value, Position::default());
+            assert_eq!(describe(&var.variable_type), expected);
+            assert!(var.nullable);
+            let var = Variable::copy_type(&var, None, var.variable_type.clone(), Position::default());
+            assert_eq!(describe(&var.variable_type), expected);
+        }
+
+        let var = declare("u8", "1").unwrap();
+        let error = catch_error(|| Variable::copy_type(&var, None, VariableType::I8(Some(1)), Position::default())).unwrap_err();
+        assert_eq!(error.message, "Can't assign to variable x: expected u8, found i8");
+        assert!(catch_error(|| Variable::copy_type(&var, None, VariableType::U8(None), Position::default())).unwrap_err().message.contains("non-null"));
+    }
+}
@@ -1,56 +1,214 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
 use substring::Substring;
-use crate::event::event_pipeline::EventPipeline;
-
-use crate::event::standard_events::{Event, get_event};
-use crate::util::{runtime_error, runtime_error_str, runtime_error_unknown_pos};
+use crate::builder::VMBuilder;
+use crate::event::event_pipeline::{EventListener, EventPipeline, MONITOR_PRIORITY};
+use crate::event::registry::EventRegistry;
+use crate::event::scheduler::SharedScheduler;
+use crate::event::custom_event::CustomEvent;
+use crate::event::schema::{EventField, EventSchema};
+use crate::event::standard_events::{check_exit_code, Event, OnError};
+use crate::util::{catch_error, catch_halt, halt, runtime_error, runtime_error_str, runtime_error_unknown_pos, RuntimeError, RUNTIME_ERROR_CODE};
 use crate::util::debug::debug;
 use crate::util::position::Position;
 use crate::util::scope::Scope;
-use crate::util::token::{Token, TokenType};
-use crate::util::variables::{Variable, VariableType};
+use crate::util::token::{EventParam, Function, Token, TokenType};
+use crate::util::variables::{EnumType, EnumValue, field_access, function_call, literal_value, type_value_to_string, Variable, VariableType};
+
+//Process exit codes of the binary, #Exit rejects them so they always mean the script failed
+//The script couldn't be read or parsed
+pub const PARSE_ERROR_EXIT: i32 = 2;
+//The checker found errors, invalid enum, custom event, function and match declarations included
+pub const TYPE_ERROR_EXIT: i32 = 3;
+//A runtime error no try or OnError handled
+pub const RUNTIME_ERROR_EXIT: i32 = 4;
 
 pub struct VM {
-    pub pipeline: EventPipeline
+    pipeline: EventPipeline,
+    enums: HashMap<String, EnumType>,
+    registry: EventRegistry,
+    queue: RefCell<VecDeque<(String, Box<dyn Event>)>>,
+    scheduler: SharedScheduler,
+    exited: Cell<bool>,
+    //The @memory variables, the only state listeners share
+    globals: RefCell<Scope>,
+    functions: HashMap<String, Function>,
+    //Return types of the running function calls, innermost last
+    calls: RefCell<Vec<Option<String>>>,
+    //Set by return until the call it ends is over, Some(None) for a return without a value
+    returning: RefCell<Option<Option<VariableType>>>,
+    //How many try blocks are running, their errors skip OnError even in nested listeners
+    trying: Cell<usize>
 }
 
+//Deep recursion is almost always a bug, this stops it before the native stack runs out
+pub const MAX_CALL_DEPTH: usize = 256;
+
 impl VM {
-    pub fn start(&mut self) {
-        let scopes = &mut vec![Scope::default()];
-        self.call_event(None, scopes, &String::from("OnStart"), &None);
+    pub fn builder() -> VMBuilder {
+        VMBuilder::new()
     }
 
-    fn call_event(&self, current_event: Option<(Position, &Box<dyn Event>)>, scopes: &mut Vec<Scope>, name: &String, params: &Option<Vec<String>>) -> Box<dyn Event> {
-        if let Some(mut event) = get_event(name) {
-            if let Some((pos, current_event)) = current_event {
-                let mut count: u8 = 0;
-                if let Some(params) = params {
-                    params.iter().for_each(|param| {
-                        let var = get_dynamic_value(name, current_event, param, pos, scopes);
-                        if !event.accept(count, var) {
-                            runtime_error(format!("Invalid event parameter ({}) for event {}", param, name), pos);
-                        }
-                        count += 1;
+    pub(crate) fn new(pipeline: EventPipeline, enums: HashMap<String, EnumType>, registry: EventRegistry, scheduler: SharedScheduler, functions: HashMap<String, Function>) -> Self {
+        VM {
+            pipeline,
+            enums,
+            registry,
+            queue: RefCell::new(VecDeque::new()),
+            scheduler,
+            exited: Cell::new(false),
+            globals: RefCell::new(Scope::default()),
+            functions,
+            calls: RefCell::new(vec![]),
+            returning: RefCell::new(None),
+            trying: Cell::new(0)
+        }
+    }
+
+    //Every method that runs the script returns the runtime errors no try or OnError handled
+    pub fn start(&self) -> Result<(), RuntimeError> {
+        self.fire("OnStart", vec![]).map(|_| ())
+    }
+
+    //Runs the @memory block, its initializers are static values so the event is never read
+    pub(crate) fn init_memory(&self) {
+        if let (Some(listeners), Some(mut event)) = (self.pipeline.listeners.get("@memory"), self.registry.get(&String::from("OnStart"))) {
+            let mut globals = self.globals.borrow_mut();
+            let mut frame = Frame::new(&mut globals, vec![]);
+            listeners.iter().for_each(|listener| if let EventListener::Script { tokens, .. } = listener {
+                tokens.iter()
+                    .filter(|token| !matches!(token.token, TokenType::ScopeStart | TokenType::ScopeEnd))
+                    .for_each(|token| {
+                        self.execute_token(&mut event, &mut frame.scopes, token);
                     });
-                }
-                if !event.check_param_count(count) {
-                    runtime_error(format!("Incorrect event parameter count for event {}", name), pos);
+            });
+        }
+    }
+
+    //Runs the script and then every scheduled event until none are left
+    //Returns the exit code, 0 unless the script called Exit with another one
+    pub fn run(&self) -> Result<i32, RuntimeError> {
+        self.start()?;
+        while !self.halted() && self.advance_to_next()? {}
+        let code = self.exit_code().unwrap_or(0);
+        self.exit(code)?;
+        Ok(code)
+    }
+
+    //The code passed to Exit, or to exit() if that happened first
+    pub fn exit_code(&self) -> Option<i32> {
+        self.scheduler.borrow().exit_code()
+    }
+
+    //Drops everything queued or scheduled and fires OnExit, only the first call does anything
+    pub fn exit(&self, code: i32) -> Result<(), RuntimeError> {
+        if !self.exited.replace(true) {
+            self.scheduler.borrow_mut().request_exit(code);
+            self.queue.borrow_mut().clear();
+            self.fire("OnExit", vec![code.into()])?;
+        }
+        Ok(())
+    }
+
+    //Exit was called and OnExit didn't start yet, so nothing else may run
+    fn halted(&self) -> bool {
+        !self.exited.get() && self.exit_code().is_some()
+    }
+
+    pub fn now(&self) -> u64 {
+        self.scheduler.borrow().now()
+    }
+
+    //Moves the time forward, firing every timer that is due on the way
+    pub fn advance(&self, millis: u64) -> Result<(), RuntimeError> {
+        catch_error(|| {
+            let until = self.now() + millis;
+            loop {
+                let timer = self.scheduler.borrow_mut().pop_due(until);
+                if let Some(timer) = timer {
+                    self.scheduler.borrow_mut().wait_until(timer.due);
+                    let event = self.prepare(timer.event.as_str(), vec![]).unwrap_or_else(|msg| {
+                        runtime_error_unknown_pos(format!("Can't fire scheduled event: {}", msg));
+                        unreachable!()
+                    });
+                    self.dispatch_global(&timer.event, event);
+                    self.drain_unchecked();
+                } else {
+                    break;
                 }
             }
-            event.call();
-            if let Some(listeners) = self.pipeline.listeners.get(event.name()) {
-                for listener in listeners {
-                    let mut cancel = false;
-                    listener.tokens.iter().for_each(|token| {
-                        if self.execute_token(&event, scopes, &token) {
-                            cancel = true;
-                        }
-                    });
-                    if cancel {
-                        break;
+            if !self.halted() {
+                self.scheduler.borrow_mut().wait_until(until);
+            }
+        })
+    }
+
+    //Jumps to the next pending timer, returns false if nothing is scheduled
+    pub fn advance_to_next(&self) -> Result<bool, RuntimeError> {
+        let next = self.scheduler.borrow().next_due();
+        if let Some(due) = next {
+            self.advance(due.saturating_sub(self.now()))?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    //Dispatches queued events until the queue is empty, events queued by their listeners included
+    pub fn drain(&self) -> Result<(), RuntimeError> {
+        catch_error(|| self.drain_unchecked())
+    }
+
+    fn drain_unchecked(&self) {
+        loop {
+            let next = self.queue.borrow_mut().pop_front();
+            if self.halted() {
+                self.queue.borrow_mut().clear();
+                break;
+            } else if let Some((name, event)) = next {
+                self.dispatch_global(&name, event);
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn call_event(&self, current_event: Option<(Position, &dyn Event)>, scopes: &mut Vec<Scope>, name: &String, params: &Option<Vec<EventParam>>) -> Box<dyn Event> {
+        let event = self.prepare_event(current_event, scopes, name, params);
+        self.dispatch(name, event, &mut scopes[0])
+    }
+
+    //Params are resolved right away, so queued events see the values from when they were queued
+    fn queue_event(&self, current_event: Option<(Position, &dyn Event)>, scopes: &mut Vec<Scope>, name: &String, params: &Option<Vec<EventParam>>) {
+        let event = self.prepare_event(current_event, scopes, name, params);
+        self.queue.borrow_mut().push_back((name.clone(), event));
+    }
+
+    fn prepare_event(&self, current_event: Option<(Position, &dyn Event)>, scopes: &mut Vec<Scope>, name: &String, params: &Option<Vec<EventParam>>) -> Box<dyn Event> {
+        if let Some(mut event) = self.registry.get(name) {
+            if let Some((pos, current_event)) = current_event {
+                let schema = event.schema();
+                let params = schema.bind(params.as_deref().unwrap_or_default()).unwrap_or_else(|msg| {
+                    runtime_error(msg, pos);
+                    unreachable!()
+                });
+                params.iter().enumerate().for_each(|(idx, param)| {
+                    let var = if function_call(param).is_some() {
+                        self.source_value(name, current_event, param, pos, scopes)
+                    } else {
+                        param_value(schema.get_param(idx), name, current_event, param, pos, scopes)
+                    };
+                    if !event.accept(idx as u8, var) {
+                        runtime_error(format!("Invalid event parameter ({}) for event {}, expected {}", param, name, schema.signature()), pos);
                     }
+                });
+                if !event.check_param_count(params.len() as u8) {
+                    runtime_error(format!("Incorrect event parameter count for event {}, expected {}", name, event.schema().signature()), pos);
+                }
+                if let Err(msg) = self.check_event(name, event.as_ref()) {
+                    runtime_error(msg, pos);
                 }
             }
-
             event
         } else if let Some((pos,_)) = current_event {
             runtime_error(format!(r#"No such event: {}"#, name), pos);
@@ -61,31 +219,159 @@ impl VM {
         }
     }
 
-    fn execute_token(&self, current_event: &Box<dyn Event>, scopes: &mut Vec<Scope>, token: &Token) -> bool {
+    //Fires an event from Rust and drains the queue afterwards
+    //Invalid events and params are errors without a position
+    pub fn fire(&self, name: &str, params: Vec<VariableType>) -> Result<Box<dyn Event>, RuntimeError> {
+        catch_error(|| {
+            let event = self.prepare(name, params).unwrap_or_else(|msg| {
+                runtime_error_unknown_pos(msg);
+                unreachable!()
+            });
+            let event = self.dispatch_global(&name.to_string(), event);
+            self.drain_unchecked();
+            event
+        })
+    }
+
+    pub fn enqueue(&self, name: &str, params: Vec<VariableType>) -> Result<(), RuntimeError> {
+        let event = self.prepare(name, params).map_err(|message| RuntimeError {
+            message,
+            code: RUNTIME_ERROR_CODE,
+            pos: None
+        })?;
+        self.queue.borrow_mut().push_back((name.to_string(), event));
+        Ok(())
+    }
+
+    //Params are positional, missing params fall back to their defaults
+    fn prepare(&self, name: &str, params: Vec<VariableType>) -> Result<Box<dyn Event>, String> {
+        let name = name.to_string();
+        let mut event = self.registry.get(&name).ok_or_else(|| format!("No such event: {}", name))?;
+        let schema = event.schema();
+        let defaults = schema.params.iter().skip(params.len())
+            .map_while(|field| field.default.as_ref().and_then(|default| literal_value(&field.typ, default)));
+        let params = params.into_iter().chain(defaults).collect::<Vec<VariableType>>();
+        for (idx, param) in params.iter().enumerate() {
+            if !event.accept(idx as u8, param.clone()) {
+                return Err(format!("Invalid event parameter ({}) for event {}, expected {}", type_value_to_string(param).unwrap_or(String::from("null")), name, schema.signature()));
+            }
+        }
+        if !event.check_param_count(params.len() as u8) {
+            return Err(format!("Incorrect event parameter count for event {}, expected {}", name, schema.signature()));
+        }
+        self.check_event(&name, event.as_ref())?;
+        Ok(event)
+    }
+
+    //Timers fire their event without params, so it has to exist and work without them when it's scheduled
+    //Exit codes are checked here so the error has the position of the #Exit
+    fn check_event(&self, name: &str, event: &dyn Event) -> Result<(), String> {
+        match (name, event.get_var("event"), event.get_var("code")) {
+            ("Schedule", Some(VariableType::String(Some(target))), _) => self.prepare(&target, vec![])
+                .map(|_| ())
+                .map_err(|msg| format!("Can't schedule {}: {}", target, msg)),
+            ("Exit", _, Some(VariableType::I32(Some(code)))) => check_exit_code(code),
+            _ => Ok(())
+        }
+    }
+
+    fn dispatch_global(&self, name: &String, event: Box<dyn Event>) -> Box<dyn Event> {
+        self.dispatch(name, event, &mut self.globals.borrow_mut())
+    }
+
+    //globals is the outermost scope of the caller, it is moved into each listener's frame while it runs
+    fn dispatch(&self, name: &String, mut event: Box<dyn Event>, globals: &mut Scope) -> Box<dyn Event> {
+        event.call();
+        if let Some(listeners) = self.pipeline.listeners.get(name) {
+            let mut cancelled = false;
+            for listener in listeners {
+                if self.halted() {
+                    break;
+                } else if listener.priority() == MONITOR_PRIORITY {
+                    self.run_listener(name, listener, &mut event.clone(), globals);
+                } else if !cancelled {
+                    cancelled = self.run_listener(name, listener, &mut event, globals);
+                }
+            }
+        }
+        event
+    }
+
+    //Every listener gets a fresh frame, only the globals are shared with the caller
+    //Changes to the event's fields are seen by the following listeners and returned to the caller
+    //Errors in listeners go to the OnError listeners if there are any, the remaining listeners still run
+    //Inside a try block they go to its catch instead, even from listeners of events the block called
+    fn run_listener(&self, name: &String, listener: &EventListener, event: &mut Box<dyn Event>, globals: &mut Scope) -> bool {
+        let mut frame = Frame::new(globals, vec![]);
+        if name == "OnError" || self.trying.get() > 0 || !self.pipeline.listeners.contains_key("OnError") {
+            return self.run_listener_unchecked(listener, event, &mut frame.scopes);
+        }
+        let result = catch_error(|| self.run_listener_unchecked(listener, event, &mut frame.scopes));
+        drop(frame);
+        match result {
+            Ok(cancel) => cancel,
+            Err(error) => {
+                self.dispatch(&String::from("OnError"), Box::new(OnError::from_error(&error)), globals);
+                false
+            }
+        }
+    }
+
+    fn run_listener_unchecked(&self, listener: &EventListener, event: &mut Box<dyn Event>, scopes: &mut Vec<Scope>) -> bool {
+        match listener {
+            //Exit in a function halts the rest of the listener, like it skips the tokens after #Exit
+            EventListener::Script { tokens, .. } => catch_halt(|| {
+                let mut cancel = false;
+                tokens.iter().for_each(|token| {
+                    if self.execute_token(event, scopes, token) {
+                        cancel = true;
+                    }
+                });
+                cancel
+            }).unwrap_or(true),
+            EventListener::Native { callback, .. } => callback(event.as_ref())
+        }
+    }
+
+    fn execute_token(&self, current_event: &mut Box<dyn Event>, scopes: &mut Vec<Scope>, token: &Token) -> bool {
+        if self.halted() {
+            return true;
+        } else if self.returning.borrow().is_some() {
+            return false;
+        }
         debug(format!("EXECUTE: {:?}", token));
         match &token.token {
             TokenType::ScopeStart => {
                 scopes.push(Scope::default());
             }
             TokenType::ScopeEnd => {
-                scopes.pop();
-                if scopes.is_empty() {
+                if scopes.len() <= 1 {
                     runtime_error_str("Global Scope dropped", token.pos);
                 }
+                scopes.pop();
             }
             TokenType::InitVariable(name, typ, is_static_value, var_cast, value) => {
-                if find_var(name, scopes).is_some() {
+                if in_current_scope(name, scopes) {
                     runtime_error(format!("Variable {} already exists in this scope", name), token.pos);
                 }
                 if let Some(value) = value {
-                    let var = if *is_static_value {
+                    let var = if let Some(enum_type) = self.enum_type(typ) {
+                        let value = if *is_static_value && value == "null" {
+                            VariableType::Enum(enum_type.name.clone(), None)
+                        } else if *is_static_value {
+                            self.enum_value(value, &None, token.pos, scopes)
+                        } else {
+                            self.source_value(name, current_event.as_ref(), value, token.pos, scopes)
+                        };
+                        Variable::new_enum(name, typ, value, token.pos)
+                    } else if *is_static_value {
                         Variable::new(name, typ, value, token.pos)
                     } else {
-                        let value = get_dynamic_value(name, current_event, value, token.pos, scopes);
+                        let value = self.source_value(name, current_event.as_ref(), value, token.pos, scopes);
                         Variable::new_type(name, typ, *var_cast, value, token.pos)
                     };
                     if let Some(scope) = scopes.last_mut() {
-                        scope.stack.push(var);
+                        scope.declare(var);
                     } else {
                         runtime_error(format!("Can't create variable {} (type {}) without a scope", name, typ), token.pos);
                     }
@@ -94,106 +380,978 @@ impl VM {
                 }
             }
             TokenType::VariableStaticSet(name, value) => {
-                replace_var_static(name, value, token.pos, scopes);
+                if let Some(VariableType::Enum(enum_name, _)) = get_var_value(name, scopes) {
+                    let value = if value == "null" {
+                        VariableType::Enum(enum_name, None)
+                    } else {
+                        self.enum_value(value, &None, token.pos, scopes)
+                    };
+                    replace_var_enum(name, value, token.pos, scopes);
+                } else {
+                    replace_var_static(name, value, token.pos, scopes);
+                }
             }
             TokenType::VariableDynamicSet(name, source, var_cast) => {
-                if let Some((target_scope_index, target_var_index)) = find_var(name, scopes) {
-                    let value = get_dynamic_value(name, current_event, source, token.pos, scopes);
+                if is_current_event(name, current_event.as_ref()) {
+                    //ConsoleInput <- line; sets the field line to the variable line, casts work like for variables
+                    let value = get_var_value(source, scopes).unwrap_or_else(|| {
+                        runtime_error(format!(r#"Variable "{}" not found in current scope!"#, source), token.pos);
+                        unreachable!()
+                    });
+                    let field = current_event.get_var(source).unwrap_or_else(|| {
+                        runtime_error(format!("Event parameter {} not found in event {} (available: {})", source, current_event.name(), field_names(current_event.as_ref())), token.pos);
+                        unreachable!()
+                    });
+                    let value = Variable::copy_type(&Variable {
+                        name: source.clone(),
+                        variable_type: field,
+                        nullable: true
+                    }, *var_cast, value, token.pos).variable_type;
+                    if !current_event.set_var(source, value) {
+                        runtime_error(format!("Can't set field {} of event {} (available: {})", source, current_event.name(), field_names(current_event.as_ref())), token.pos);
+                    }
+                } else if find_var(name, scopes).is_some() {
+                    let value = self.source_value(name, current_event.as_ref(), source, token.pos, scopes);
 
-                    let target_scope = scopes.get_mut(target_scope_index).unwrap();
-                    let target_var = target_scope.stack.remove(target_var_index);
-                    target_scope.stack.insert(target_var_index, Variable::copy_type(target_var, *var_cast, value, token.pos));
+                    if let Some(target_var) = find_var_mut(name, scopes) {
+                        *target_var = if let VariableType::Enum(_, _) = target_var.variable_type {
+                            Variable::copy_enum(target_var, value, token.pos)
+                        } else {
+                            Variable::copy_type(target_var, *var_cast, value, token.pos)
+                        };
+                    }
                 } else {
                     runtime_error(format!(r#"Variable "{}" not found in current scope!"#, name), token.pos);
                 }
             }
             TokenType::VariableEventSet(name, event, params) => {
-                if let Some((target_scope_index, target_var_index)) = find_var(name, scopes) {
-                    let event = self.call_event(Some((token.pos, current_event)), scopes, event, params);
-
-                    let target_scope = scopes.get_mut(target_scope_index).unwrap();
-                    target_scope.stack.remove(target_var_index);
+                if find_var(name, scopes).is_some() {
+                    let event = self.call_event(Some((token.pos, current_event.as_ref())), scopes, event, params);
 
-                    target_scope.stack.insert(target_var_index, Variable::new_event_type(name, event));
+                    if let Some(target_var) = find_var_mut(name, scopes) {
+                        *target_var = Variable::new_event_type(name, event);
+                    }
                 } else {
                     runtime_error(format!(r#"Variable "{}" not found in current scope!"#, name), token.pos);
                 }
             }
             TokenType::CallEvent(name, params) => {
-                self.call_event(Some((token.pos, current_event)), scopes, name, params);
+                self.call_event(Some((token.pos, current_event.as_ref())), scopes, name, params);
+            }
+            TokenType::QueueEvent(name, params) => {
+                self.queue_event(Some((token.pos, current_event.as_ref())), scopes, name, params);
             }
             TokenType::InitVariableEvent(var_name, name, params) => {
-                let event = self.call_event(Some((token.pos, current_event)), scopes, name, params);
+                if in_current_scope(var_name, scopes) {
+                    runtime_error(format!("Variable {} already exists in this scope", var_name), token.pos);
+                }
+                let event = self.call_event(Some((token.pos, current_event.as_ref())), scopes, name, params);
                 if let Some(scope) = scopes.last_mut() {
-                    scope.stack.push(Variable::new_event_type(var_name, event));
+                    scope.declare(Variable::new_event_type(var_name, event));
                 } else {
                     runtime_error(format!("Can't create variable {} (type event) without a scope", name), token.pos);
                 }
             }
+            TokenType::InitVariableOutputs(bindings, name, params) => {
+                if let Some((_, var_name)) = bindings.iter().find(|(_, var_name)| in_current_scope(var_name, scopes)) {
+                    runtime_error(format!("Variable {} already exists in this scope", var_name), token.pos);
+                }
+                let event = self.call_event(Some((token.pos, current_event.as_ref())), scopes, name, params);
+                let schema = event.schema();
+                bindings.iter().for_each(|(output, var_name)| {
+                    let var = Variable {
+                        name: var_name.clone(),
+                        variable_type: read_field(event.as_ref(), output, token.pos),
+                        nullable: schema.get_output(output).is_some_and(|field| field.typ.starts_with('?'))
+                    };
+                    if let Some(scope) = scopes.last_mut() {
+                        scope.declare(var);
+                    } else {
+                        runtime_error(format!("Can't create variable {} without a scope", var_name), token.pos);
+                    }
+                });
+            }
+            TokenType::InitVariableEnum(name, typ, variant, params) => {
+                if in_current_scope(name, scopes) {
+                    runtime_error(format!("Variable {} already exists in this scope", name), token.pos);
+                }
+                if self.enum_type(typ).is_none() {
+                    runtime_error(format!("Can't assign enum {} to variable {} of type {}", variant, name, typ), token.pos);
+                }
+                let var = Variable::new_enum(name, typ, self.enum_value(variant, params, token.pos, scopes), token.pos);
+                if let Some(scope) = scopes.last_mut() {
+                    scope.declare(var);
+                } else {
+                    runtime_error(format!("Can't create variable {} (type {}) without a scope", name, typ), token.pos);
+                }
+            }
+            TokenType::VariableEnumSet(name, variant, params) => {
+                let value = self.enum_value(variant, params, token.pos, scopes);
+                replace_var_enum(name, value, token.pos, scopes);
+            }
+            TokenType::Try(tokens, catch, catch_tokens) => {
+                let depth = scopes.len();
+                scopes.push(Scope::default());
+                let result = catch_error(|| {
+                    let _try = TryBlock::enter(&self.trying);
+                    let mut cancel = false;
+                    tokens.iter().for_each(|token| {
+                        if self.execute_token(current_event, scopes, token) {
+                            cancel = true;
+                        }
+                    });
+                    cancel
+                });
+                scopes.truncate(depth);
+                match result {
+                    Ok(true) => return true,
+                    Ok(false) => {}
+                    Err(error) => {
+                        let mut scope = Scope::default();
+                        if let Some(catch) = catch {
+                            scope.declare(Variable::new_event_type(catch, Box::new(OnError::from_error(&error))));
+                        }
+                        scopes.push(scope);
+                        let mut cancel = false;
+                        catch_tokens.iter().for_each(|token| {
+                            if self.execute_token(current_event, scopes, token) {
+                                cancel = true;
+                            }
+                        });
+                        scopes.pop();
+                        if cancel {
+                            return true;
+                        }
+                    }
+                }
+            }
+            TokenType::Match(name, arms) => {
+                match get_var_value(name, scopes) {
+                    Some(VariableType::Enum(enum_name, Some(value))) => {
+                        let variant = format!("{}::{}", enum_name, value.variant);
+                        if let Some(arm) = arms.iter().find(|arm| arm.variant == variant || arm.variant == "_") {
+                            let mut scope = Scope::default();
+                            if arm.variant != "_" {
+                                arm.bindings.iter().zip(value.payload).filter(|(binding, _)| binding.as_str() != "_").for_each(|(binding, mut var)| {
+                                    var.name = binding.clone();
+                                    scope.declare(var);
+                                });
+                            }
+                            scopes.push(scope);
+                            let mut cancel = false;
+                            arm.tokens.iter().for_each(|token| {
+                                if self.execute_token(current_event, scopes, token) {
+                                    cancel = true;
+                                }
+                            });
+                            scopes.pop();
+                            if cancel {
+                                return true;
+                            }
+                        } else {
+                            runtime_error(format!("No match arm for {} in match on {}", variant, name), token.pos);
+                        }
+                    }
+                    Some(VariableType::Enum(_, None)) => {
+                        runtime_error(format!("Can't match on null variable {}", name), token.pos);
+                    }
+                    Some(_) => {
+                        runtime_error(format!("Can't match on variable {}, it is not an enum", name), token.pos);
+                    }
+                    None => {
+                        runtime_error(format!(r#"Variable "{}" not found in current scope!"#, name), token.pos);
+                    }
+                }
+            }
+            TokenType::CallFunction(name, args) => {
+                self.call_function(name, args, current_event.as_ref(), token.pos, scopes);
+            }
+            TokenType::Return(value) => {
+                let returns = self.calls.borrow().last().cloned().unwrap_or_else(|| {
+                    runtime_error_str("return outside of a function", token.pos);
+                    unreachable!()
+                });
+                let value = match (value, returns) {
+                    (Some(value), Some(typ)) => Some(self.typed_value(&String::from("return"), &typ, current_event.as_ref(), value, token.pos, scopes)),
+                    (None, None) => None,
+                    (Some(_), None) => {
+                        runtime_error_str("This function doesn't return a value", token.pos);
+                        unreachable!()
+                    }
+                    (None, Some(typ)) => {
+                        runtime_error(format!("Missing return value of type {}", typ), token.pos);
+                        unreachable!()
+                    }
+                };
+                self.returning.replace(Some(value));
+            }
             TokenType::Raw(s) => {
                 runtime_error(format!("Tried to execute unparsed instruction: {}", s), token.pos);
             }
             _ => {}
         }
-        debug(format!("CURRENT SCOPES: {:?}", scopes));
+        //Formatting every scope after every token is linear in the number of variables, so release builds skip it
+        if cfg!(debug_assertions) {
+            debug(format!("CURRENT SCOPES: {:?}", scopes));
+        }
         false
     }
+
+    //Function calls can be used anywhere a value is read from a variable or event
+    fn source_value(&self, name: &String, current_event: &dyn Event, source: &String, pos: Position, scopes: &mut Vec<Scope>) -> VariableType {
+        if let Some((function, args)) = function_call(source) {
+            self.call_function(&function, &args, current_event, pos, scopes).unwrap_or_else(|| {
+                runtime_error(format!("Function {} doesn't return a value", function), pos);
+                unreachable!()
+            })
+        } else {
+            get_dynamic_value(name, current_event, source, pos, scopes)
+        }
+    }
+
+    //Literals are read as typ, everything else has to fit it
+    fn typed_value(&self, name: &String, typ: &String, current_event: &dyn Event, source: &String, pos: Position, scopes: &mut Vec<Scope>) -> VariableType {
+        let is_literal = get_var_value(source, scopes).is_none() && function_call(source).is_none();
+        if let Some(enum_type) = self.enum_type(typ) {
+            let value = if is_literal && source == "null" {
+                VariableType::Enum(enum_type.name.clone(), None)
+            } else if is_literal && source.contains("::") {
+                self.enum_value(source, &None, pos, scopes)
+            } else {
+                self.source_value(name, current_event, source, pos, scopes)
+            };
+            return Variable::new_enum(name, typ, value, pos).variable_type;
+        }
+        let literal = if is_literal && source == "null" {
+            return Variable::new(name, typ, source, pos).variable_type;
+        } else if is_literal {
+            literal_value(typ, source)
+        } else {
+            None
+        };
+        let value = literal.unwrap_or_else(|| self.source_value(name, current_event, source, pos, scopes));
+        Variable::new_type(name, typ, None, value, pos).variable_type
+    }
+
+    //Arguments are read in the caller's frame, the body runs in a new one with the globals and its params
+    //Functions don't handle an event, so this in their body is an empty one
+    fn call_function(&self, name: &String, args: &[String], current_event: &dyn Event, pos: Position, scopes: &mut Vec<Scope>) -> Option<VariableType> {
+        let function = self.functions.get(name).unwrap_or_else(|| {
+            runtime_error(format!("No such function: {}", name), pos);
+            unreachable!()
+        });
+        if args.len() != function.params.len() {
+            runtime_error(format!("Function {} takes {} argument(s), got {}", name, function.params.len(), args.len()), pos);
+        }
+        if self.calls.borrow().len() >= MAX_CALL_DEPTH {
+            runtime_error(format!("Maximum function call depth of {} exceeded in function {}", MAX_CALL_DEPTH, name), pos);
+        }
+        let mut locals = Scope::default();
+        function.params.iter().zip(args).for_each(|((param, typ), arg)| {
+            let value = self.typed_value(param, typ, current_event, arg, pos, scopes);
+            locals.declare(Variable {
+                name: param.clone(),
+                variable_type: value,
+                nullable: typ.starts_with('?')
+            });
+        });
+
+        //Both guards are undone when an error unwinds out of the body
+        let mut frame = Frame::new(&mut scopes[0], vec![locals]);
+        let call = Call::enter(&self.calls, function.returns.clone());
+        let mut event: Box<dyn Event> = Box::new(CustomEvent::new(EventSchema::new(format!("fn {}", name).as_str(), "Function call")));
+        function.tokens.iter().for_each(|token| {
+            self.execute_token(&mut event, &mut frame.scopes, token);
+        });
+        drop(call);
+        drop(frame);
+        let value = self.returning.take().flatten();
+        //Exit stopped the body, the instruction that called it can't go on without its value
+        if self.halted() {
+            halt();
+        }
+        match (&function.returns, value) {
+            (Some(typ), None) => {
+                runtime_error(format!("Function {} ended without returning a value (type {})", name, typ), pos);
+                unreachable!()
+            }
+            (_, value) => value
+        }
+    }
+
+    fn enum_type(&self, typ: &str) -> Option<&EnumType> {
+        self.enums.get(typ.strip_prefix('?').unwrap_or(typ))
+    }
+
+    fn enum_value(&self, variant: &String, params: &Option<Vec<EventParam>>, pos: Position, scopes: &[Scope]) -> VariableType {
+        let (enum_name, variant_name) = variant.split_once("::").unwrap_or_else(|| {
+            runtime_error(format!("{} is not an enum variant", variant), pos);
+            unreachable!()
+        });
+        let enum_type = self.enums.get(enum_name).unwrap_or_else(|| {
+            runtime_error(format!("No such enum: {}", enum_name), pos);
+            unreachable!()
+        });
+        let enum_variant = enum_type.variants.iter().find(|v| v.name == variant_name).unwrap_or_else(|| {
+            runtime_error(format!("Enum {} has no variant {}", enum_name, variant_name), pos);
+            unreachable!()
+        });
+
+        let params = enum_variant.schema(&enum_type.name).bind(params.as_deref().unwrap_or_default()).unwrap_or_else(|msg| {
+            runtime_error(msg, pos);
+            unreachable!()
+        });
+        if params.len() != enum_variant.fields.len() {
+            runtime_error(format!("Enum variant {} takes {} value(s), got {}", variant, enum_variant.fields.len(), params.len()), pos);
+        }
+        let payload = enum_variant.fields.iter().zip(params.iter()).map(|((field, typ), param)| {
+            if let Some(value) = get_var_value(param, scopes) {
+                Variable::new_type(field, typ, None, value, pos)
+            } else {
+                Variable::new(field, typ, param, pos)
+            }
+        }).collect::<Vec<Variable>>();
+
+        VariableType::Enum(enum_type.name.clone(), Some(EnumValue {
+            variant: enum_variant.name.clone(),
+            payload
+        }))
+    }
 }
 
-fn get_dynamic_value(name: &String, current_event: &Box<dyn Event>, source: &String, pos: Position, scopes: &Vec<Scope>) -> VariableType {
-    if current_event.name() == source.as_str() {
-        todo!()
-    } else if let Some(var) = get_var_value(source, scopes) {
-        if let VariableType::Event(event) = var {
-            if let Some(var) = event.get_var(name) {
-                var
+//The scopes of a listener run or function call, the outermost one is moved in from the caller
+//It goes back when the frame is dropped, so errors unwinding past the caller don't lose it
+struct Frame<'a> {
+    outer: &'a mut Scope,
+    scopes: Vec<Scope>
+}
+
+impl<'a> Frame<'a> {
+    fn new(outer: &'a mut Scope, locals: Vec<Scope>) -> Self {
+        let scopes = std::iter::once(std::mem::take(outer)).chain(locals).collect();
+        Frame {
+            outer,
+            scopes
+        }
+    }
+}
+
+impl Drop for Frame<'_> {
+    fn drop(&mut self) {
+        if !self.scopes.is_empty() {
+            *self.outer = self.scopes.swap_remove(0);
+        }
+    }
+}
+
+//An entry on the call stack, popped when the call ends or unwinds
+struct Call<'a> {
+    calls: &'a RefCell<Vec<Option<String>>>
+}
+
+impl<'a> Call<'a> {
+    fn enter(calls: &'a RefCell<Vec<Option<String>>>, returns: Option<String>) -> Self {
+        calls.borrow_mut().push(returns);
+        Call {
+            calls
+        }
+    }
+}
+
+impl Drop for Call<'_> {
+    fn drop(&mut self) {
+        self.calls.borrow_mut().pop();
+    }
+}
+
+//A running try block, counted until it ends or unwinds
+struct TryBlock<'a> {
+    trying: &'a Cell<usize>
+}
+
+impl<'a> TryBlock<'a> {
+    fn enter(trying: &'a Cell<usize>) -> Self {
+        trying.set(trying.get() + 1);
+        TryBlock {
+            trying
+        }
+    }
+}
+
+impl Drop for TryBlock<'_> {
+    fn drop(&mut self) {
+        self.trying.set(self.trying.get() - 1);
+    }
+}
+
+fn param_value(field: Option<&EventField>, name: &String, current_event: &dyn Event, source: &String, pos: Position, scopes: &[Scope]) -> VariableType {
+    if get_var_value(source, scopes).is_none() {
+        if let Some(value) = field.and_then(|field| literal_value(&field.typ, source)) {
+            return value;
+        }
+    }
+    get_dynamic_value(name, current_event, source, pos, scopes)
+}
+
+//The event a listener handles can be used by its name or as this
+fn is_current_event(source: &String, current_event: &dyn Event) -> bool {
+    source == "this" || current_event.name() == source.as_str()
+}
+
+fn field_names(event: &dyn Event) -> String {
+    event.schema().outputs.iter().map(|field| field.name.clone()).collect::<Vec<String>>().join(", ")
+}
+
+fn read_field(event: &dyn Event, field: &String, pos: Position) -> VariableType {
+    event.get_var(field).unwrap_or_else(|| {
+        runtime_error(format!("Event parameter {} not found in event {} (available: {})", field, event.name(), field_names(event)), pos);
+        unreachable!()
+    })
+}
+
+fn get_dynamic_value(name: &String, current_event: &dyn Event, source: &String, pos: Position, scopes: &[Scope]) -> VariableType {
+    if let Some((var, field)) = field_access(source) {
+        if is_current_event(&var, current_event) {
+            read_field(current_event, &field, pos)
+        } else if let Some(value) = get_var_value(&var, scopes) {
+            if let VariableType::Event(event) = value {
+                read_field(event.as_ref(), &field, pos)
             } else {
-                runtime_error(format!(r#"Event parameter {} not found in event {}"#, name, current_event.name()), pos);
+                runtime_error(format!("Variable {} is not an event, can't read field {}", var, field), pos);
                 unreachable!()
             }
+        } else {
+            runtime_error(format!(r#"Variable "{}" not found in current scope!"#, var), pos);
+            unreachable!()
+        }
+    } else if is_current_event(source, current_event) {
+        read_field(current_event, name, pos)
+    } else if let Some(var) = get_var_value(source, scopes) {
+        if let VariableType::Event(event) = var {
+            read_field(event.as_ref(), name, pos)
         } else {
             var
         }
     } else if source.starts_with('"') && source.ends_with('"') {
         VariableType::String(Some(source.substring(1, source.len() - 1).to_string()))
+    } else if let Some(value) = literal_value("any", source) {
+        //Number and bool literals, so they can be cast (a (u8) <=| 300;)
+        value
     } else {
         runtime_error(format!(r#"Invalid dynamic source for {}: {}"#, name, source), pos);
         unreachable!()
     }
 }
 
-fn replace_var_static(name: &String, value: &String, pos: Position, scopes: &mut Vec<Scope>) {
-    if let Some((scope_index, var_index)) = find_var(name, scopes) {
-        let scope = scopes.get_mut(scope_index).unwrap();
-        let var = scope.stack.remove(var_index);
-        scope.stack.insert(var_index, Variable::copy(var, value, pos));
+fn replace_var_static(name: &String, value: &String, pos: Position, scopes: &mut [Scope]) {
+    if let Some(var) = find_var_mut(name, scopes) {
+        *var = Variable::copy(var, value, pos);
     } else {
         runtime_error(format!(r#"Variable "{}" not found in current scope!"#, name), pos);
     }
 }
 
-fn find_var(name: &String, scopes: &Vec<Scope>) -> Option<(usize, usize)> {
-    let mut scope_index = 0;
-    for scope in scopes {
-        let mut var_index = 0;
-        for var in &scope.stack {
-            if var.name.eq(name) {
-                return Some((scope_index, var_index));
-            }
-            var_index += 1;
-        }
-        scope_index += 1;
+fn replace_var_enum(name: &String, value: VariableType, pos: Position, scopes: &mut [Scope]) {
+    if let Some(var) = find_var_mut(name, scopes) {
+        *var = Variable::copy_enum(var, value, pos);
+    } else {
+        runtime_error(format!(r#"Variable "{}" not found in current scope!"#, name), pos);
     }
-    None
 }
 
-fn get_var_value(name: &String, scopes: &Vec<Scope>) -> Option<VariableType> {
-    for scope in scopes {
-        for var in &scope.stack {
-            if var.name == *name {
-                return Some(var.variable_type.clone());
-            }
+//Innermost scope first, so inner variables shadow outer ones
+fn find_var<'a>(name: &String, scopes: &'a [Scope]) -> Option<&'a Variable> {
+    scopes.iter().rev().find_map(|scope| scope.get(name))
+}
+
+fn find_var_mut<'a>(name: &String, scopes: &'a mut [Scope]) -> Option<&'a mut Variable> {
+    scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name))
+}
+
+fn in_current_scope(name: &String, scopes: &[Scope]) -> bool {
+    scopes.last().is_some_and(|scope| scope.contains(name))
+}
+
+fn get_var_value(name: &String, scopes: &[Scope]) -> Option<VariableType> {
+    find_var(name, scopes).map(|var| var.variable_type.clone())
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::util::variables::type_value_to_string;
+    use crate::builder::VMBuilder;
+    use crate::vm::{MAX_CALL_DEPTH, VM};
+
+    //A builder for the script that collects every line it prints
+    pub(crate) fn printing(source: &str) -> (VMBuilder, Rc<RefCell<Vec<String>>>) {
+        let lines = Rc::new(RefCell::new(vec![]));
+        let printed = lines.clone();
+        let builder = VM::builder().source(source)
+            .monitor("Print", move |event| printed.borrow_mut().push(event.get_var("message").and_then(|message| type_value_to_string(&message)).unwrap_or_default()));
+        (builder, lines)
+    }
+
+    //Runs the script like the simulation does and returns every line it printed
+    pub(crate) fn printed(source: &str) -> Vec<String> {
+        let (builder, lines) = printing(source);
+        builder.build().unwrap().run().unwrap();
+        lines.take()
+    }
+
+    #[test]
+    fn errors_in_try_skip_on_error() {
+        let source = "\
+@custom {
+    Boom {
+    }
+}
+Boom {
+    y (u8) <= \"300\";
+    #Print <- \"after boom\";
+}
+OnError {
+    #Print <- \"OnError \" OnError.message;
+}
+OnStart {
+    try {
+        #Boom;
+        #Print <- \"not reached\";
+    } catch err {
+        #Print <- \"caught \" err.message;
+    }
+    #Boom;
+    #Print <- \"after uncaught boom\";
+}
+";
+        assert_eq!(printed(source), vec!["caught Can't cast 300 to u8: out of range", "OnError Can't cast 300 to u8: out of range", "after uncaught boom"]);
+    }
+
+    #[test]
+    fn caught_errors_bind_message_code_and_position() {
+        let source = "\
+OnStart {
+    try {
+        y (u8) <= \"300\";
+    } catch err {
+        #Print <- err.message;
+        #Print <- err.code;
+        #Print <- err.position;
+    }
+    code (i32) = 256;
+    try {
+        #Exit <- code;
+    } catch failed {
+        message (string) <- failed;
+        #Print <- message \" \" failed.code \" \" failed.position;
+    }
+}
+";
+        assert_eq!(printed(source), vec!["Can't cast 300 to u8: out of range", "1", "3:10", "Exit code 256 is out of range, expected 0 to 255 1 11:9"]);
+    }
+
+    #[test]
+    fn globals_come_back_after_listener_errors() {
+        let source = "\
+@memory {
+    count (i128) = 0;
+}
+@custom {
+    Boom {
+    }
+    Show {
+    }
+}
+Boom {
+    (result -> next) <- #+ <- count, 1;
+    count <- next;
+    y (u8) <= \"300\";
+}
+Show {
+    #Print <- count;
+}
+";
+        let (builder, lines) = printing(source);
+        let vm = builder.build().unwrap();
+        vm.start().unwrap();
+        assert!(vm.fire("Boom", vec![]).is_err());
+        assert!(vm.fire("Boom", vec![]).is_err());
+        vm.fire("Show", vec![]).unwrap();
+        assert_eq!(lines.take(), vec!["2"]);
+
+        //With an OnError listener the error is handled in the middle of the firing listener, which keeps using the globals
+        let source = format!("{}OnError {{\n}}\nOnStart {{\n    #Boom;\n    #Print <- count;\n    #Show;\n}}\n", source);
+        assert_eq!(printed(&source), vec!["1", "1"]);
+    }
+
+    #[test]
+    fn native_listeners_run_by_priority_and_can_cancel() {
+        let source = "\
+@custom {
+    Ping {
+    }
+}
+Ping {
+    #Print <- \"script\";
+}
+";
+        let (builder, lines) = printing(source);
+        let (before, after, cancel) = (lines.clone(), lines.clone(), lines.clone());
+        let vm = builder
+            .listener("Ping", move |_| {
+                after.borrow_mut().push(String::from("after"));
+                false
+            })
+            .listener_with_priority("Ping", 10, move |_| {
+                before.borrow_mut().push(String::from("before"));
+                false
+            })
+            .listener_with_priority("Ping", -10, move |_| {
+                cancel.borrow_mut().push(String::from("cancel"));
+                true
+            })
+            .listener_with_priority("Ping", -20, |_| panic!("the event was cancelled"))
+            .build()
+            .unwrap();
+        vm.fire("Ping", vec![]).unwrap();
+        assert_eq!(lines.take(), vec!["before", "script", "after", "cancel"]);
+    }
+
+    #[test]
+    fn script_listeners_run_by_priority_and_monitors_get_a_copy() {
+        let source = "\
+@custom {
+    Ping {
+        n (i32) = 0;
+    }
+}
+Ping [priority=monitor] {
+    n (i32) = 7;
+    this <- n;
+    #Print <- \"monitor \" this.n;
+}
+Ping [priority=low] {
+    #Print <- \"low\";
+}
+Ping {
+    #Print <- \"normal\";
+}
+Ping [priority=-150] {
+    #Print <- \"cancelled\";
+}
+Ping [priority=high] {
+    #Print <- \"high\";
+}
+Ping {
+    #Print <- \"normal again\";
+}
+";
+        let (builder, lines) = printing(source);
+        //Cancels between the low and the -150 listener
+        let vm = builder.listener_with_priority("Ping", -120, |_| true).build().unwrap();
+        let event = vm.fire("Ping", vec![]).unwrap();
+        assert_eq!(lines.take(), vec!["high", "normal", "normal again", "low", "monitor 7"]);
+        assert_eq!(event.get_var("n").and_then(|n| type_value_to_string(&n)), Some(String::from("0")));
+    }
+
+    #[test]
+    fn queued_events_run_after_the_listener_in_order() {
+        let source = "\
+@custom {
+    Later {
+        n (i32);
+    }
+    Last {
+    }
+}
+Later {
+    #Print <- \"later \" this.n;
+    #>Last;
+}
+Last {
+    #Print <- \"last\";
+}
+OnStart {
+    n (i32) = 1;
+    #>Later <- n;
+    n = 2;
+    #>Later <- n;
+    #Print <- \"start\";
+}
+";
+        assert_eq!(printed(source), vec!["start", "later 1", "later 2", "last", "last"]);
+
+        let (builder, lines) = printing(source);
+        let vm = builder.build().unwrap();
+        vm.enqueue("Later", vec![5.into()]).unwrap();
+        assert_eq!(vm.enqueue("Nope", vec![]).unwrap_err().message, "No such event: Nope");
+        assert!(lines.borrow().is_empty());
+        vm.drain().unwrap();
+        assert_eq!(lines.take(), vec!["later 5", "last"]);
+    }
+
+    #[test]
+    fn on_exit_runs_once_with_the_code() {
+        let source = "\
+@custom {
+    Later {
+    }
+    Never {
+    }
+}
+OnStart {
+    #Schedule <- \"Later\", 10;
+    #Schedule <- \"Never\", 20;
+    #Print <- \"start\";
+}
+Later {
+    #>Never;
+    #Exit <- 7;
+    #Print <- \"not after exit\";
+}
+Never {
+    #Print <- \"never\";
+}
+OnExit {
+    #Print <- \"bye \" this.code;
+}
+";
+        let (builder, lines) = printing(source);
+        let vm = builder.build().unwrap();
+        assert_eq!(vm.run().unwrap(), 7);
+        vm.exit(9).unwrap();
+        assert_eq!(lines.take(), vec!["start", "bye 7"]);
+        assert_eq!(vm.exit_code(), Some(7));
+
+        assert_eq!(printed("OnExit {\n    #Print <- \"bye \" this.code;\n}\n"), vec!["bye 0"]);
+    }
+
+    #[test]
+    fn every_listener_run_gets_its_own_frame() {
+        let source = "\
+@memory {
+    runs (i128) = 0;
+}
+@custom {
+    Ping {
+    }
+}
+Ping {
+    local (string) = \"ping\";
+    (result -> next) <- #+ <- runs, 1;
+    runs <- next;
+    #Print <- local \" \" runs;
+}
+OnStart {
+    local (i32) = 1;
+    #Ping;
+    #Ping;
+    #Print <- local \" \" runs;
+}
+";
+        assert_eq!(printed(source), vec!["ping 1", "ping 2", "1 2"]);
+    }
+
+    #[test]
+    fn listeners_read_and_write_their_event() {
+        let source = "\
+@custom {
+    Greet {
+        name (string);
+    }
+}
+Greet [priority=high] {
+    name (string) <- Greet;
+    #Print <- \"hello \" name;
+    name = \"Bob\";
+    Greet <- name;
+}
+Greet {
+    #Print <- \"hello \" this.name;
+    name (string) = \"Eve\";
+    this <- name;
+}
+";
+        let (builder, lines) = printing(source);
+        let vm = builder.build().unwrap();
+        let event = vm.fire("Greet", vec!["Ann".into()]).unwrap();
+        assert_eq!(lines.take(), vec!["hello Ann", "hello Bob"]);
+        assert_eq!(event.get_var("name").and_then(|name| type_value_to_string(&name)), Some(String::from("Eve")));
+    }
+
+    #[test]
+    fn outputs_bind_to_new_variables() {
+        let source = "\
+OnStart {
+    (result -> total, num1) <- #+ <- 2, 3;
+    (time) <- #Now;
+    #Print <- total \" \" num1 \" \" time;
+}
+";
+        assert_eq!(printed(source), vec!["5 2 0"]);
+    }
+
+    #[test]
+    fn inner_scopes_shadow_outer_ones() {
+        let source = "\
+OnStart {
+    x (i32) = 1;
+    try {
+        x (string) = \"inner\";
+        #Print <- x;
+        x = \"assigned\";
+        #Print <- x;
+    } catch err {
+    }
+    #Print <- x;
+    try {
+        x = 2;
+    } catch err {
+    }
+    #Print <- x;
+}
+";
+        assert_eq!(printed(source), vec!["inner", "assigned", "1", "2"]);
+    }
+
+    #[test]
+    fn wildcard_arms_take_the_other_variants() {
+        let source = "\
+@enum {
+    State {
+        Idle;
+        Running ticks (u32);
+        Failed message (string);
+    }
+}
+OnStart {
+    state (State) <- State::Failed <- \"bad\";
+    match state {
+        State::Running ticks {
+            #Print <- ticks;
         }
+        _ {
+            #Print <- \"not running\";
+        }
+    }
+    state <- State::Running <- 5;
+    match state {
+        State::Running ticks {
+            #Print <- ticks;
+        }
+        _ {
+            #Print <- \"not running\";
+        }
+    }
+}
+";
+        assert_eq!(printed(source), vec!["not running", "5"]);
     }
-    None
-}
\ No newline at end of file
+
+    #[test]
+    fn functions_return_values() {
+        let source = "\
+fn double(value (i128)) -> i128 {
+    (result -> doubled) <- #* <- value, 2;
+    return doubled;
+}
+fn sign(value (i128)) -> string {
+    try {
+        small (u8) <= value;
+    } catch err {
+        return \"negative\";
+    }
+    return \"positive\";
+}
+OnStart {
+    twice (i128) <- double(21);
+    #Print <- twice;
+    #Print <- sign(-3);
+    #Print <- sign(double(2));
+}
+";
+        assert_eq!(printed(source), vec!["42", "negative", "positive"]);
+    }
+
+    #[test]
+    fn recursion_is_limited() {
+        let source = "\
+@custom {
+    Ping {
+    }
+}
+fn forever(depth (i128)) -> i128 {
+    (result -> next) <- #+ <- depth, 1;
+    deeper (i128) <- forever(next);
+    return deeper;
+}
+fn depth() -> i32 {
+    return 1;
+}
+OnStart {
+    deepest (i128) <- forever(0);
+}
+Ping {
+    #Print <- depth();
+}
+";
+        //Every call nests a few interpreter frames, the test thread's stack is too small for the full depth
+        std::thread::Builder::new().stack_size(8 << 20).spawn(move || {
+            let (builder, lines) = printing(source);
+            let vm = builder.build().unwrap();
+            let error = vm.fire("OnStart", vec![]).unwrap_err();
+            assert_eq!(error.message, format!("Maximum function call depth of {} exceeded in function forever", MAX_CALL_DEPTH));
+            //The unwound calls are gone, the next call starts at the bottom again
+            vm.fire("Ping", vec![]).unwrap();
+            assert_eq!(lines.take(), vec!["1"]);
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn globals_come_back_after_calls() {
+        let source = "\
+@memory {
+    count (i128) = 0;
+}
+fn bump() {
+    (result -> next) <- #+ <- count, 1;
+    count <- next;
+}
+fn bump_and_fail() {
+    bump();
+    small (u8) <= \"300\";
+}
+OnStart {
+    bump();
+    bump();
+    #Print <- count;
+    try {
+        bump_and_fail();
+    } catch err {
+        #Print <- count;
+    }
+    #Print <- count;
+}
+";
+        assert_eq!(printed(source), vec!["2", "3", "3"]);
+    }
+
+    #[test]
+    fn math_events_reject_u128_values_above_i128() {
+        let source = format!("OnStart {{\n    big (u128) = {};\n    sum (event) <- #+ <- big, 1;\n    #Print <- sum.result;\n}}\n", i128::MAX as u128 + 1);
+        let vm = VM::builder().source(&source).build().unwrap();
+        assert_eq!(vm.fire("OnStart", vec![]).unwrap_err().to_string(), "Invalid event parameter (big) for event +, expected #+ <- num1 (number), num2 (number) (at 3:26)");
+        let source = format!("OnStart {{\n    big (u128) = {};\n    sum (event) <- #- <- big, 1;\n    #Print <- sum.result;\n}}\n", i128::MAX);
+        assert_eq!(printed(&source), vec![(i128::MAX - 1).to_string()]);
+    }
+
+    #[test]
+    fn exit_codes_are_checked_at_the_call() {
+        let vm = VM::builder().source("OnStart {\n    code (i32) = 256;\n    #Exit <- code;\n}\n").build().unwrap();
+        assert_eq!(vm.fire("OnStart", vec![]).unwrap_err().to_string(), "Exit code 256 is out of range, expected 0 to 255 (at 3:9)");
+        assert_eq!(vm.exit_code(), None);
+        assert!(vm.fire("Exit", vec![3.into()]).unwrap_err().message.contains("reserved"));
+        assert!(vm.fire("Exit", vec![7.into()]).is_ok());
+        assert_eq!(vm.exit_code(), Some(7));
+    }
+}